@@ -1,15 +1,24 @@
 use std::{
-    cell::RefCell,
+    any::Any,
+    cell::{Cell, Ref, RefCell, RefMut},
     collections::HashSet,
-    ops::{AddAssign, Mul, MulAssign, Sub},
+    ops::{Add, AddAssign, Mul, MulAssign, Sub},
     time::Duration,
 };
 
-use sdl2::{keyboard::Keycode, render::Canvas, video::Window};
+use crate::event::GameEvent;
+use crate::input::{Action, AnalogInput};
+use crate::math_helper::GameRng;
 
-use crate::actors::{wall::WallType, scoreboard::ScoreboardData};
+use crate::actors::{
+    ball::{BallData, BallState},
+    player_paddle::{PaddleData, PaddleState},
+    scoreboard::ScoreboardData,
+    wall::WallType,
+};
+use crate::renderer::Renderer;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub struct Vec2 {
     pub x: f32,
     pub y: f32,
@@ -23,6 +32,10 @@ impl Vec2 {
     pub fn length(self) -> f32 {
         self.length_squared().sqrt()
     }
+
+    pub fn dot(self, other: Vec2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
 }
 
 impl Mul<f32> for Vec2 {
@@ -42,6 +55,17 @@ impl MulAssign<f32> for Vec2 {
     }
 }
 
+impl Add for Vec2 {
+    type Output = Vec2;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Vec2 {
+            x: self.x + rhs.x,
+            y: self.y + rhs.y,
+        }
+    }
+}
+
 impl AddAssign for Vec2 {
     fn add_assign(&mut self, rhs: Self) {
         self.x += rhs.x;
@@ -121,25 +145,361 @@ impl Collider {
     }
 }
 
-pub struct UpdateInfo<'a> {
-    pub keys_pressed: HashSet<Keycode>,
+/// Names a single actor for as long as it exists, however the actors around it change.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ActorId(u64);
+
+impl ActorId {
+    /// The id written out as a number, e.g. to a snapshot file.
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    /// An id read back from `as_u64`.
+    pub fn from_u64(id: u64) -> ActorId {
+        ActorId(id)
+    }
+}
+
+/// A change to the actors, queued during an update and applied once it is over.
+pub enum Command {
+    Spawn(ActorId, Box<dyn Actor>),
+    Despawn(ActorId),
+}
+
+pub struct UpdateInfo {
+    pub actions_pressed: HashSet<Action>,
+    pub analog: AnalogInput,
     pub elapsed: Duration,
     pub elapsed_sec_f32: f32,
-    pub actors: Vec<RefCell<Box<dyn Actor + 'a>>>,
+    pub actors: Vec<RefCell<Box<dyn Actor>>>,
+    pub commands: RefCell<Vec<Command>>,
+    /// Events waiting to be handed out
+    pub events: RefCell<Vec<GameEvent>>,
+    pub rng: RefCell<GameRng>,
+    /// The actor being updated right now
+    pub current: Option<ActorId>,
+    /// The id of every actor in `actors`, in the same order
+    ids: Vec<ActorId>,
+    next_id: Cell<u64>,
+}
+
+impl UpdateInfo {
+    pub fn new(rng: GameRng) -> UpdateInfo {
+        UpdateInfo {
+            actions_pressed: HashSet::new(),
+            analog: AnalogInput::default(),
+            elapsed: Duration::from_nanos(0),
+            elapsed_sec_f32: 0.0,
+            actors: Vec::new(),
+            commands: RefCell::new(Vec::new()),
+            events: RefCell::new(Vec::new()),
+            rng: RefCell::new(rng),
+            current: Option::None,
+            ids: Vec::new(),
+            next_id: Cell::new(0),
+        }
+    }
+
+    /// Adds an actor straight away. Only for setting up, actors that are updating
+    /// have to use `spawn`.
+    pub fn add(&mut self, actor: Box<dyn Actor>) -> ActorId {
+        let id = self.next_id();
+        self.actors.push(RefCell::new(actor));
+        self.ids.push(id);
+        id
+    }
+
+    /// Adds an actor once the current update is over. The id is valid right away,
+    /// so it can be handed out before the actor exists.
+    pub fn spawn(&self, actor: Box<dyn Actor>) -> ActorId {
+        let id = self.next_id();
+        self.commands.borrow_mut().push(Command::Spawn(id, actor));
+        id
+    }
+
+    /// Removes an actor once the current update is over.
+    pub fn despawn(&self, id: ActorId) {
+        self.commands.borrow_mut().push(Command::Despawn(id));
+    }
+
+    pub fn emit(&self, event: GameEvent) {
+        self.events.borrow_mut().push(event);
+    }
+
+    /// Applies every queued spawn and despawn, in the order they were asked for.
+    pub fn apply_commands(&mut self) {
+        let commands = std::mem::take(self.commands.get_mut());
+        for c in commands {
+            match c {
+                Command::Spawn(id, actor) => {
+                    self.actors.push(RefCell::new(actor));
+                    self.ids.push(id);
+                }
+                Command::Despawn(id) => {
+                    if let Option::Some(i) = self.index_of(id) {
+                        self.actors.remove(i);
+                        self.ids.remove(i);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The id of every actor, in update order.
+    pub fn ids(&self) -> &[ActorId] {
+        &self.ids
+    }
+
+    /// How many ids have been handed out, so a snapshot can carry on numbering from there.
+    pub fn ids_handed_out(&self) -> u64 {
+        self.next_id.get()
+    }
+
+    /// Takes every actor out along with its id, in update order.
+    pub fn take_actors(&mut self) -> Vec<(ActorId, Box<dyn Actor>)> {
+        let ids = std::mem::take(&mut self.ids);
+        let actors = std::mem::take(&mut self.actors);
+        ids.into_iter()
+            .zip(actors.into_iter().map(|a| a.into_inner()))
+            .collect()
+    }
+
+    /// Replaces every actor, e.g. when going back to a snapshot. `actors` must be in id
+    /// order and `ids_handed_out` past all of them.
+    pub fn set_actors(&mut self, actors: Vec<(ActorId, Box<dyn Actor>)>, ids_handed_out: u64) {
+        self.commands.get_mut().clear();
+        self.ids.clear();
+        self.actors.clear();
+        for (id, actor) in actors {
+            self.ids.push(id);
+            self.actors.push(RefCell::new(actor));
+        }
+        self.next_id.set(ids_handed_out);
+    }
+
+    pub fn id_at(&self, index: usize) -> Option<ActorId> {
+        self.ids.get(index).copied()
+    }
+
+    pub fn index_of(&self, id: ActorId) -> Option<usize> {
+        // Ids are handed out in order and actors are only ever appended
+        self.ids.binary_search(&id).ok()
+    }
+
+    /// The actor with `id`, if it still exists.
+    pub fn get(&self, id: ActorId) -> Option<&RefCell<Box<dyn Actor>>> {
+        self.index_of(id).and_then(|i| self.actors.get(i))
+    }
+
+    /// The actor with `id` as a `T`, unless it is some other type or already borrowed.
+    pub fn get_as<T: Actor>(&self, id: ActorId) -> Option<Ref<'_, T>> {
+        self.get(id).and_then(|a| downcast(a.try_borrow().ok()?))
+    }
+
+    pub fn get_as_mut<T: Actor>(&self, id: ActorId) -> Option<RefMut<'_, T>> {
+        self.get(id).and_then(|a| downcast_mut(a.try_borrow_mut().ok()?))
+    }
+
+    /// The first actor of type `T`. Actors that are already borrowed, like the one
+    /// being updated, are skipped by every query.
+    pub fn find<T: Actor>(&self) -> Option<Ref<'_, T>> {
+        self.all::<T>().next()
+    }
+
+    pub fn find_mut<T: Actor>(&self) -> Option<RefMut<'_, T>> {
+        self.actors
+            .iter()
+            .find_map(|a| downcast_mut(a.try_borrow_mut().ok()?))
+    }
+
+    /// Every actor of type `T`, in update order.
+    pub fn all<T: Actor>(&self) -> impl Iterator<Item = Ref<'_, T>> {
+        self.actors
+            .iter()
+            .filter_map(|a| downcast(a.try_borrow().ok()?))
+    }
+
+    /// Every actor with `tag` along with its id, in update order.
+    pub fn tagged(&self, tag: Tag) -> impl Iterator<Item = (ActorId, Ref<'_, Box<dyn Actor>>)> {
+        self.actors
+            .iter()
+            .zip(self.ids.iter())
+            .filter_map(move |(a, id)| match a.try_borrow() {
+                Result::Ok(actor) if actor.has_tag(tag) => Option::Some((*id, actor)),
+                _ => Option::None,
+            })
+    }
+
+    fn next_id(&self) -> ActorId {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        ActorId(id)
+    }
+}
+
+fn downcast<T: Actor>(actor: Ref<'_, Box<dyn Actor>>) -> Option<Ref<'_, T>> {
+    Ref::filter_map(actor, |a| (**a).as_any().downcast_ref::<T>()).ok()
+}
+
+fn downcast_mut<T: Actor>(actor: RefMut<'_, Box<dyn Actor>>) -> Option<RefMut<'_, T>> {
+    RefMut::filter_map(actor, |a| (**a).as_any_mut().downcast_mut::<T>()).ok()
+}
+
+/// What an actor is, for finding actors without knowing their type.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Tag {
+    Ball,
+    Paddle,
+    /// Walls the ball bounces off
+    Wall,
+    /// Walls behind the paddles, scoring when the ball reaches them
+    Goal,
+    Scoreboard,
 }
 
 pub enum ActorData {
+    Ball(BallData),
+    Paddle(PaddleData),
     Wall(WallType),
     Scoreboard(ScoreboardData)
 }
 
-pub trait Actor {
+/// Everything about an actor that changes while a match is played, enough to put it
+/// back exactly the way it was.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ActorState {
+    Ball(BallState),
+    Paddle(PaddleState),
+    Scoreboard(ScoreboardData),
+    BallSpawner { spawn_in: f32 },
+}
+
+/// Lets actors be turned back into their own type. Implemented for every actor.
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+pub trait Actor: AsAny + 'static {
     fn position(&self) -> &Vec2;
     fn set_position(&mut self, new_pos: &Vec2);
     fn update(&mut self, info: &UpdateInfo);
     fn fixed_update(&mut self, info: &UpdateInfo);
-    fn draw(&self, canvas: &mut Canvas<Window>) -> Result<(), String>;
+    fn draw(&self, renderer: &mut dyn Renderer) -> Result<(), String>;
     fn get_collider(&self) -> Option<Collider>;
     fn get_data(&self) -> Option<ActorData>;
     fn set_data(&mut self, data: ActorData);
+
+    fn has_tag(&self, _tag: Tag) -> bool {
+        false
+    }
+
+    /// Called for every event emitted by any actor, including this one.
+    fn on_event(&mut self, _event: &GameEvent, _info: &UpdateInfo) {}
+
+    /// The actor's current state, or None for actors that never change.
+    fn save_state(&self) -> Option<ActorState> {
+        Option::None
+    }
+
+    /// Goes back to a state returned by `save_state`. States of other actors are ignored.
+    fn load_state(&mut self, _state: &ActorState) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::actors::scoreboard::Scoreboard;
+    use crate::actors::wall::{Wall, WallType};
+
+    fn wall_at(x: f32) -> Box<dyn Actor> {
+        Box::new(Wall::new(
+            &Vec2 { x, y: 0.0 },
+            10.0,
+            10.0,
+            WallType::Regular,
+        ))
+    }
+
+    fn xs(info: &UpdateInfo) -> Vec<f32> {
+        info.actors
+            .iter()
+            .map(|a| a.borrow().position().x)
+            .collect()
+    }
+
+    #[test]
+    fn ids_stay_the_same_when_other_actors_are_despawned() {
+        let mut info = UpdateInfo::new(GameRng::seed_from_u64(1));
+        let ids: Vec<ActorId> = (0..4).map(|i| info.add(wall_at(i as f32))).collect();
+        info.despawn(ids[0]);
+        info.despawn(ids[2]);
+        info.apply_commands();
+
+        assert_eq!(info.ids(), [ids[1], ids[3]]);
+        assert_eq!(info.get(ids[3]).unwrap().borrow().position().x, 3.0);
+        assert!(info.get(ids[0]).is_none() && info.get(ids[2]).is_none());
+        // Ids of despawned actors aren't handed out again
+        let new = info.add(wall_at(4.0));
+        assert!(!ids.contains(&new));
+        assert_eq!(info.index_of(new), Option::Some(2));
+    }
+
+    #[test]
+    fn queued_commands_apply_in_order_after_the_update() {
+        let mut info = UpdateInfo::new(GameRng::seed_from_u64(1));
+        let first = info.add(wall_at(0.0));
+        let spawned = info.spawn(wall_at(1.0));
+        info.despawn(first);
+        let later = info.spawn(wall_at(2.0));
+        // Despawning an actor that is still queued removes it once it has been added
+        let short_lived = info.spawn(wall_at(3.0));
+        info.despawn(short_lived);
+        assert_eq!(xs(&info), [0.0]);
+        assert!(info.get(spawned).is_none());
+
+        info.apply_commands();
+        assert_eq!(xs(&info), [1.0, 2.0]);
+        assert_eq!(info.ids(), [spawned, later]);
+        assert!(info.commands.borrow().is_empty());
+    }
+
+    #[test]
+    fn queries_skip_the_actor_being_updated() {
+        let mut info = UpdateInfo::new(GameRng::seed_from_u64(1));
+        let first = info.add(wall_at(0.0));
+        let second = info.add(wall_at(1.0));
+        let goal = info.add(Box::new(Wall::new(
+            &Vec2 { x: 2.0, y: 0.0 },
+            10.0,
+            10.0,
+            WallType::Left,
+        )));
+        assert_eq!(info.all::<Wall>().count(), 3);
+        assert_eq!(info.get_as::<Wall>(second).unwrap().position().x, 1.0);
+        assert!(info.get_as::<Scoreboard>(second).is_none());
+
+        let _updating = info.get(first).unwrap().borrow_mut();
+        assert_eq!(info.find::<Wall>().unwrap().position().x, 1.0);
+        assert_eq!(info.all::<Wall>().count(), 2);
+        assert!(info.get_as::<Wall>(first).is_none());
+        assert_eq!(info.find_mut::<Wall>().unwrap().position().x, 1.0);
+        let walls: Vec<ActorId> = info.tagged(Tag::Wall).map(|(id, _)| id).collect();
+        assert_eq!(walls, [second]);
+        let goals: Vec<ActorId> = info.tagged(Tag::Goal).map(|(id, _)| id).collect();
+        assert_eq!(goals, [goal]);
+    }
 }
@@ -1,72 +1,232 @@
-use sdl2::{pixels::Color, rect::Point};
+use rand::Rng;
 
 use crate::actor::*;
-use crate::collision_helper;
-use crate::math_helper;
+use crate::collision_helper::{self, SweepHit};
+use crate::controllers::local::Side;
+use crate::event::GameEvent;
+use crate::math_helper::{self, GameRng};
+use crate::renderer::{Color, Renderer};
+use crate::rules::MatchRules;
+use crate::tuning::{BallTuning, MultiBall};
+use crate::world::GameSettings;
 
+use super::player_paddle::PaddleData;
+use super::scoreboard::{Scoreboard, ScoreboardData};
 use super::wall::WallType;
 
-const INITIAL_SPEED: f32 = 125.0;
+/// Stops a ball wedged between surfaces from bouncing forever within one update
+const MAX_BOUNCES_PER_UPDATE: u32 = 8;
+const RADIUS: f32 = 23.0;
+
+#[derive(Copy, Clone)]
+pub struct BallData {
+    pub velocity: Vec2,
+    pub radius: f32,
+    /// False while waiting to be served
+    pub in_play: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Phase {
+    /// Resting in the middle until it is served towards `towards`
+    Waiting {
+        serve_in: f32,
+        towards: Side,
+    },
+    InPlay,
+    /// Someone won the match, so there are no more serves
+    MatchOver,
+}
+
+/// Everything about a ball that changes during a match. The rest comes from the settings.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct BallState {
+    pub position: Vec2,
+    pub initial_position: Vec2,
+    pub velocity: Vec2,
+    pub phase: Phase,
+    pub rally_hits: u32,
+    pub alive: bool,
+}
 
 pub struct Ball {
     position: Vec2,
     initial_position: Vec2,
     velocity: Vec2,
     radius: f32,
+    rules: MatchRules,
+    tuning: BallTuning,
+    multi_ball: MultiBall,
+    phase: Phase,
+    /// Paddle returns since the last serve
+    rally_hits: u32,
+    /// Cleared when the ball leaves the field, hiding it until it is despawned
+    alive: bool,
 }
 
 impl Ball {
-    pub fn new(position: &Vec2) -> Ball {
+    pub fn new(position: &Vec2, settings: &GameSettings, rng: &mut GameRng) -> Ball {
+        let rules = settings.rules;
+        let server = rules.next_server(0, Option::None, rng);
         Ball {
             position: *position,
             initial_position: *position,
-            velocity: Ball::get_random_starting_velocity(),
-            radius: 23.0,
+            velocity: Vec2 { x: 0.0, y: 0.0 },
+            radius: RADIUS,
+            rules,
+            tuning: settings.ball,
+            multi_ball: settings.multi_ball,
+            rally_hits: 0,
+            alive: true,
+            phase: Phase::Waiting {
+                serve_in: rules.serve_delay,
+                towards: rules.serve_towards(server, rng),
+            },
         }
     }
 
-    fn get_random_starting_velocity() -> Vec2 {
+    /// Recreates a ball from a state saved with `save_state`.
+    pub fn from_state(state: &BallState, settings: &GameSettings) -> Ball {
+        Ball {
+            position: state.position,
+            initial_position: state.initial_position,
+            velocity: state.velocity,
+            radius: RADIUS,
+            rules: settings.rules,
+            tuning: settings.ball,
+            multi_ball: settings.multi_ball,
+            phase: state.phase,
+            rally_hits: state.rally_hits,
+            alive: state.alive,
+        }
+    }
+
+    /// Creates a ball that is already in play, served in a random direction.
+    pub fn new_served(position: &Vec2, settings: &GameSettings, rng: &mut GameRng) -> Ball {
+        let mut ball = Ball::new(position, settings, rng);
+        let towards = if rng.gen::<bool>() {
+            Side::Left
+        } else {
+            Side::Right
+        };
+        ball.serve(towards, rng);
+        ball
+    }
+
+    pub fn velocity(&self) -> Vec2 {
+        self.velocity
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// False while waiting to be served
+    pub fn in_play(&self) -> bool {
+        self.phase == Phase::InPlay
+    }
+
+    /// Launches the ball at a random angle towards `towards`.
+    fn serve(&mut self, towards: Side, rng: &mut GameRng) {
+        self.velocity = Ball::get_random_starting_velocity(rng) * self.tuning.initial_speed;
+        self.rally_hits = 0;
+        self.velocity.x = match towards {
+            Side::Left => -self.velocity.x.abs(),
+            Side::Right => self.velocity.x.abs(),
+        };
+        self.phase = Phase::InPlay;
+    }
+
+    /// Puts the ball back in the middle to wait for the next serve, or stops it for good
+    /// once the match is over.
+    fn reset_after_point(
+        &mut self,
+        scores: Option<ScoreboardData>,
+        scorer: Side,
+        rng: &mut GameRng,
+    ) {
+        self.position = self.initial_position;
+        self.velocity = Vec2 { x: 0.0, y: 0.0 };
+
+        let points_played = scores.map(|s| s.points_played).unwrap_or(0);
+        self.phase = match scores.and_then(|s| self.rules.match_winner(&s)) {
+            Option::Some(_) => Phase::MatchOver,
+            Option::None => {
+                let server = self
+                    .rules
+                    .next_server(points_played, Option::Some(scorer), rng);
+                Phase::Waiting {
+                    serve_in: self.rules.serve_delay,
+                    towards: self.rules.serve_towards(server, rng),
+                }
+            }
+        };
+    }
+
+    /// The further from the middle of the paddle the ball hits, the steeper it leaves.
+    fn deflect(&mut self, normal: Vec2, paddle_position: Vec2, paddle: PaddleData) {
+        let speed = self.velocity.length();
+        let max_angle = self.tuning.max_bounce_angle.to_radians();
+        let direction = normal.x.signum();
+
+        let reach = paddle.size.height / 2.0 + self.radius;
+        let offset = ((self.position.y - paddle_position.y) / reach).clamp(-1.0, 1.0);
+        let angle = offset * max_angle;
+
+        let vy = speed * angle.sin() + paddle.velocity.y * self.tuning.paddle_influence;
+        let vx = speed * angle.cos();
+        let angle = vy.atan2(vx).clamp(-max_angle, max_angle);
+        self.velocity = Vec2 {
+            x: direction * speed * angle.cos(),
+            y: speed * angle.sin(),
+        };
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        let current = self.velocity.length();
+        if current > 0.0 {
+            self.velocity *= speed.min(self.tuning.max_speed) / current;
+        }
+    }
+
+    fn get_random_starting_velocity(rng: &mut GameRng) -> Vec2 {
         // Ball should end up going within (0 ± FOURTH_ANGLE or 1 ± FOURTH_ANGLE) * pi
         // FOURTH_ANGLE should be less than 0.5
         const FOURTH_ANGLE: f32 = 0.3;
-        let mut theta: f32 = rand::random::<f32>() * (4.0 * FOURTH_ANGLE) - FOURTH_ANGLE;
+        let mut theta: f32 = rng.gen::<f32>() * (4.0 * FOURTH_ANGLE) - FOURTH_ANGLE;
         if theta > FOURTH_ANGLE {
             theta += 1.0 - (2.0 * FOURTH_ANGLE);
         }
         theta *= std::f32::consts::PI;
         let v = math_helper::get_point_on_unit_circle(theta);
-        Vec2 {
-            x: v.0 * INITIAL_SPEED,
-            y: v.1 * INITIAL_SPEED,
+        Vec2 { x: v.0, y: v.1 }
+    }
+
+    /// Announces the point for `scorer` and returns the new score.
+    fn score_point(&self, info: &UpdateInfo, scorer: Side) -> Option<ScoreboardData> {
+        let mut scores = info.find::<Scoreboard>()?.data();
+        // Another ball may have already decided the match
+        if self.rules.match_winner(&scores).is_some() {
+            return Option::Some(scores);
         }
+
+        self.rules.award_point(&mut scores, scorer);
+        info.emit(GameEvent::PointScored { scorer, scores });
+        if let Option::Some(winner) = self.rules.match_winner(&scores) {
+            info.emit(GameEvent::MatchWon { winner, scores });
+        }
+        Option::Some(scores)
     }
 
-    fn update_scoreboard(&mut self, info: &UpdateInfo, wall: WallType) {
-        let mut i = 0;
-        while i < info.actors.len() {
-            if let Option::Some(a) = info.actors.get(i) {
-                if let Result::Ok(mut actor) = a.try_borrow_mut() {
-                    if let Option::Some(data) = actor.get_data() {
-                        match data {
-                            ActorData::Scoreboard(mut s) => {
-                                match wall {
-                                    WallType::Left => {
-                                        s.left_score += 1;
-                                    }
-                                    WallType::Right => {
-                                        s.right_score += 1;
-                                    }
-                                    WallType::Regular => {}
-                                }
-                                actor.set_data(ActorData::Scoreboard(s));
-                                return;
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-            }
-            i += 1;
+    pub(crate) fn serve_event(&self) -> GameEvent {
+        GameEvent::Serve {
+            towards: if self.velocity.x < 0.0 {
+                Side::Left
+            } else {
+                Side::Right
+            },
+            position: self.position,
+            velocity: self.velocity,
         }
     }
 }
@@ -83,67 +243,181 @@ impl Actor for Ball {
     fn update(&mut self, _info: &UpdateInfo) {}
 
     fn fixed_update(&mut self, info: &UpdateInfo) {
-        self.position += self.velocity * info.elapsed_sec_f32;
-
-        let my_col = self
-            .get_collider()
-            .expect("ball did not return a collider for self");
-        let mut my_bounds = my_col.to_bounds(self.position);
-
-        let mut i = 0;
-        while i < info.actors.len() {
-            if let Option::Some(a) = info.actors.get(i) {
-                if let Result::Ok(actor) = a.try_borrow() {
-                    if let Option::Some(col) = actor.get_collider() {
-                        let bounds = col.to_bounds(*actor.position());
-                        let sep_vec = collision_helper::separation_vec(my_bounds, bounds);
-                        let sep = sep_vec.length() - self.radius;
-
-                        if sep < 0.0 {
-                            if let Option::Some(d) = actor.get_data() {
-                                if let ActorData::Wall(wd) = d {
-                                    if matches!(wd, WallType::Left) || matches!(wd, WallType::Right)
-                                    {
-                                        self.position = self.initial_position;
-                                        self.velocity = Ball::get_random_starting_velocity();
-                                        self.update_scoreboard(info, wd);
-                                        return;
-                                    }
+        match self.phase {
+            Phase::Waiting { serve_in, towards } => {
+                let serve_in = serve_in - info.elapsed_sec_f32;
+                if serve_in > 0.0 {
+                    self.phase = Phase::Waiting { serve_in, towards };
+                    return;
+                }
+                self.serve(towards, &mut info.rng.borrow_mut());
+                info.emit(self.serve_event());
+            }
+            Phase::InPlay => {
+                let speed =
+                    self.velocity.length() + self.tuning.speed_up_per_second * info.elapsed_sec_f32;
+                self.set_speed(speed);
+            }
+            Phase::MatchOver => return,
+        }
+
+        // Move in stages, stopping at every surface the ball touches on the way, so it
+        // can't skip past a paddle no matter how fast it goes
+        let mut remaining = info.elapsed_sec_f32;
+        let mut bounces = 0;
+        while remaining > 0.0 && bounces < MAX_BOUNCES_PER_UPDATE {
+            let motion = self.velocity * remaining;
+
+            let mut first_hit: Option<(SweepHit, Option<ActorData>, Vec2, Option<ActorId>)> =
+                Option::None;
+            let mut i = 0;
+            while i < info.actors.len() {
+                if let Option::Some(a) = info.actors.get(i) {
+                    if let Result::Ok(actor) = a.try_borrow() {
+                        if let Option::Some(col) = actor.get_collider() {
+                            let bounds = col.to_bounds(*actor.position());
+                            let hit = collision_helper::sweep_circle(
+                                self.position,
+                                self.radius,
+                                motion,
+                                bounds,
+                            );
+                            if let Option::Some(hit) = hit {
+                                let earlier = match &first_hit {
+                                    Option::Some((first, _, _, _)) => hit.time < first.time,
+                                    Option::None => true,
+                                };
+                                if earlier {
+                                    first_hit = Option::Some((
+                                        hit,
+                                        actor.get_data(),
+                                        *actor.position(),
+                                        info.id_at(i),
+                                    ));
                                 }
                             }
+                        }
+                    }
+                }
+                i += 1;
+            }
+
+            let (hit, data, hit_position, hit_id) = match first_hit {
+                Option::Some(h) => h,
+                Option::None => {
+                    self.position += motion;
+                    return;
+                }
+            };
+            self.position += motion * hit.time;
+            remaining *= 1.0 - hit.time;
 
-                            let speed = self.velocity.length();
-                            let t = -sep / speed;
+            if let Option::Some(ActorData::Wall(wd)) = data {
+                // Getting past a paddle scores for the other side
+                let scorer = match wd {
+                    WallType::Left => Option::Some(Side::Right),
+                    WallType::Right => Option::Some(Side::Left),
+                    WallType::Regular => Option::None,
+                };
+                if let Option::Some(scorer) = scorer {
+                    let scores = self.score_point(info, scorer);
+                    if count_balls(info) > 0 {
+                        // Play goes on with the balls that are left
+                        self.alive = false;
+                        if let Option::Some(id) = info.current {
+                            info.despawn(id);
+                        }
+                    } else {
+                        self.reset_after_point(scores, scorer, &mut info.rng.borrow_mut());
+                    }
+                    return;
+                }
+            }
 
-                            if sep_vec.x.abs() > sep_vec.y.abs() {
-                                self.velocity.x *= -1.0;
-                            } else {
-                                self.velocity.y *= -1.0;
-                            }
-                            self.velocity *= 1.05;
+            let speed = self.velocity.length();
+            match data {
+                // Any hit that sends the ball back across the field is a return, but one
+                // that only glances off the top or bottom of a paddle is a plain bounce
+                Option::Some(ActorData::Paddle(paddle)) if hit.normal.x * self.velocity.x < 0.0 => {
+                    self.deflect(hit.normal, hit_position, paddle);
+                    self.rally_hits += 1;
+                    let rally_bonus = self.tuning.rally_speed(self.rally_hits)
+                        - self.tuning.rally_speed(self.rally_hits - 1);
+                    self.set_speed(speed * self.tuning.paddle_speed_up + rally_bonus);
 
-                            self.position += self.velocity * t;
-                            my_bounds = my_col.to_bounds(self.position);
+                    let every = self.multi_ball.spawn_every_returns;
+                    if every > 0
+                        && self.rally_hits.is_multiple_of(every)
+                        && count_balls(info) + 1 < self.multi_ball.max_balls
+                    {
+                        let settings = GameSettings {
+                            rules: self.rules,
+                            ball: self.tuning,
+                            multi_ball: self.multi_ball,
+                            ..GameSettings::default()
+                        };
+                        let ball = Ball::new_served(
+                            &self.initial_position,
+                            &settings,
+                            &mut info.rng.borrow_mut(),
+                        );
+                        info.emit(ball.serve_event());
+                        info.spawn(Box::new(ball));
+                    }
+                    info.emit(GameEvent::BallHitPaddle {
+                        position: self.position,
+                        velocity: self.velocity,
+                    });
+                }
+                Option::Some(ActorData::Ball(other)) if other.in_play => {
+                    // Equal masses swap their speeds along the line between them
+                    let mine = self.velocity.dot(hit.normal);
+                    let theirs = other.velocity.dot(hit.normal);
+                    self.velocity += hit.normal * (theirs - mine);
+                    if let Option::Some(mut ball) =
+                        hit_id.and_then(|id| info.get_as_mut::<Ball>(id))
+                    {
+                        ball.velocity = other.velocity + hit.normal * (mine - theirs);
+                    }
+                    info.emit(GameEvent::BallHitBall {
+                        position: self.position,
+                        velocity: self.velocity,
+                    });
+                }
+                _ => {
+                    self.velocity =
+                        self.velocity - hit.normal * (2.0 * self.velocity.dot(hit.normal));
+                    self.set_speed(speed * self.tuning.wall_speed_up);
+                    let position = self.position;
+                    let velocity = self.velocity;
+                    match data {
+                        Option::Some(ActorData::Paddle(_)) => {
+                            info.emit(GameEvent::BallHitPaddle { position, velocity })
+                        }
+                        Option::Some(ActorData::Wall(_)) => {
+                            info.emit(GameEvent::BallHitWall { position, velocity })
                         }
+                        _ => {}
                     }
                 }
             }
-            i += 1;
+            bounces += 1;
         }
     }
 
-    fn draw(&self, canvas: &mut sdl2::render::Canvas<sdl2::video::Window>) -> Result<(), String> {
-        canvas.set_draw_color(Color::RGB(255, 255, 255));
-        draw_circle(
-            canvas,
+    fn draw(&self, renderer: &mut dyn Renderer) -> Result<(), String> {
+        renderer.fill_circle(
             self.position.x.round() as i32,
             self.position.y.round() as i32,
             self.radius.round() as i32,
-        )?;
-        Result::Ok(())
+            Color::WHITE,
+        )
     }
 
     fn get_collider(&self) -> Option<Collider> {
+        if !self.alive {
+            return Option::None;
+        }
         Option::Some(Collider {
             is_static: false,
             collider: ColliderType::Circle {
@@ -153,58 +427,306 @@ impl Actor for Ball {
     }
 
     fn get_data(&self) -> Option<ActorData> {
-        Option::None
+        if !self.alive {
+            return Option::None;
+        }
+        Option::Some(ActorData::Ball(BallData {
+            velocity: self.velocity,
+            radius: self.radius,
+            in_play: self.phase == Phase::InPlay,
+        }))
     }
 
     fn set_data(&mut self, _data: ActorData) {}
+
+    fn has_tag(&self, tag: Tag) -> bool {
+        tag == Tag::Ball
+    }
+
+    fn on_event(&mut self, event: &GameEvent, _info: &UpdateInfo) {
+        // Every ball stops once the match is decided, not just the one that decided it
+        if let GameEvent::MatchWon { .. } = event {
+            self.velocity = Vec2 { x: 0.0, y: 0.0 };
+            self.phase = Phase::MatchOver;
+        }
+    }
+
+    fn save_state(&self) -> Option<ActorState> {
+        Option::Some(ActorState::Ball(BallState {
+            position: self.position,
+            initial_position: self.initial_position,
+            velocity: self.velocity,
+            phase: self.phase,
+            rally_hits: self.rally_hits,
+            alive: self.alive,
+        }))
+    }
+
+    fn load_state(&mut self, state: &ActorState) {
+        if let ActorState::Ball(s) = state {
+            self.position = s.position;
+            self.initial_position = s.initial_position;
+            self.velocity = s.velocity;
+            self.phase = s.phase;
+            self.rally_hits = s.rally_hits;
+            self.alive = s.alive;
+        }
+    }
 }
 
-// Midpoint circle algorithm, adapted from https://stackoverflow.com/a/48291620, modified to draw a filled circle
-fn draw_circle(
-    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
-    center_x: i32,
-    center_y: i32,
-    radius: i32,
-) -> Result<(), String> {
-    let diameter = radius * 2;
-
-    let mut x = radius - 1;
-    let mut y = 0;
-    let mut tx = 1;
-    let mut ty = 1;
-    let mut error = tx - diameter;
-
-    while x >= y {
-        canvas.draw_line(
-            Point::new(center_x + x, center_y + y),
-            Point::new(center_x - x, center_y + y),
-        )?;
-        canvas.draw_line(
-            Point::new(center_x + x, center_y - y),
-            Point::new(center_x - x, center_y - y),
-        )?;
-
-        if error <= 0 {
-            y += 1;
-            error += ty;
-            ty += 2;
+/// Counts the balls on the field and waiting to spawn, except the one updating.
+pub fn count_balls(info: &UpdateInfo) -> u32 {
+    let mut count = info.all::<Ball>().filter(|b| b.alive).count();
+    for c in info.commands.borrow().iter() {
+        if let Command::Spawn(_, actor) = c {
+            if actor.has_tag(Tag::Ball) {
+                count += 1;
+            }
         }
+    }
+    count as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::actors::player_paddle::PlayerPaddle;
+    use crate::actors::wall::Wall;
+    use crate::controllers::local::LocalController;
+    use crate::world::FIXED_UPDATE_TIME_SECS_F32;
 
-        if error > 0 {
-            canvas.draw_line(
-                Point::new(center_x + y, center_y + x),
-                Point::new(center_x - y, center_y + x),
-            )?;
-            canvas.draw_line(
-                Point::new(center_x + y, center_y - x),
-                Point::new(center_x - y, center_y - x),
-            )?;
-
-            x -= 1;
-            tx += 2;
-            error += tx - diameter;
+    /// A field 800 wide and 600 high with walls all around and paddles at x 40 and 760
+    fn field() -> UpdateInfo {
+        let mut info = UpdateInfo::new(GameRng::seed_from_u64(0));
+        info.elapsed_sec_f32 = FIXED_UPDATE_TIME_SECS_F32;
+        let walls = [
+            (400.0, -300.0, WallType::Regular),
+            (400.0, 900.0, WallType::Regular),
+            (-400.0, 300.0, WallType::Left),
+            (1200.0, 300.0, WallType::Right),
+        ];
+        for (x, y, wall_type) in walls {
+            info.add(Box::new(Wall::new(&Vec2 { x, y }, 800.0, 600.0, wall_type)));
         }
+        let left = PlayerPaddle::new(
+            &Vec2 { x: 40.0, y: 300.0 },
+            Box::new(LocalController::left()),
+        );
+        info.add(Box::new(left));
+        let right = PlayerPaddle::new(
+            &Vec2 { x: 760.0, y: 300.0 },
+            Box::new(LocalController::right()),
+        );
+        info.add(Box::new(right));
+        info
     }
 
-    Result::Ok(())
+    /// A ball in play with no speed limit or speed ups, so it is easy to follow
+    fn ball(position: Vec2, velocity: Vec2) -> Ball {
+        let tuning = BallTuning {
+            max_speed: f32::INFINITY,
+            paddle_speed_up: 1.0,
+            wall_speed_up: 1.0,
+            rally_speed_up: 0.0,
+            speed_up_per_second: 0.0,
+            ..BallTuning::default()
+        };
+        tuned_ball(position, velocity, tuning)
+    }
+
+    fn tuned_ball(position: Vec2, velocity: Vec2, tuning: BallTuning) -> Ball {
+        let settings = GameSettings {
+            ball: tuning,
+            ..GameSettings::default()
+        };
+        let state = BallState {
+            position,
+            initial_position: Vec2 { x: 400.0, y: 300.0 },
+            velocity,
+            phase: Phase::InPlay,
+            rally_hits: 0,
+            alive: true,
+        };
+        Ball::from_state(&state, &settings)
+    }
+
+    #[test]
+    fn very_fast_ball_is_returned_by_the_paddle() {
+        let info = field();
+        let mut ball = ball(
+            Vec2 { x: 400.0, y: 300.0 },
+            Vec2 {
+                x: -30_000.0,
+                y: 0.0,
+            },
+        );
+        ball.fixed_update(&info);
+
+        assert!(ball.velocity.x > 0.0);
+        assert!(ball.position.x > 40.0 && ball.position.x < 760.0);
+        let events = info.events.borrow();
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, GameEvent::BallHitPaddle { .. })));
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, GameEvent::PointScored { .. })));
+    }
+
+    #[test]
+    fn ball_bounces_several_times_in_one_fixed_update() {
+        let info = field();
+        // Travels 1000 up and down in one fixed update, through a gap 554 high
+        let mut ball = ball(
+            Vec2 { x: 400.0, y: 300.0 },
+            Vec2 {
+                x: 0.0,
+                y: 60_000.0,
+            },
+        );
+        ball.fixed_update(&info);
+
+        let bounces = info
+            .events
+            .borrow()
+            .iter()
+            .filter(|e| matches!(e, GameEvent::BallHitWall { .. }))
+            .count();
+        assert_eq!(bounces, 2);
+        // Down 277 to the bottom, up 554 to the top and down the last 169
+        assert!((ball.position.y - (RADIUS + 169.0)).abs() < 0.1);
+        assert!(ball.velocity.y > 0.0);
+    }
+
+    #[test]
+    fn ball_leaves_steeper_the_further_from_the_middle_it_hits() {
+        let still = PaddleData {
+            velocity: Vec2 { x: 0.0, y: 0.0 },
+            size: RectangleDefinition {
+                width: 15.0,
+                height: 50.0,
+            },
+        };
+        let paddle = Vec2 { x: 40.0, y: 300.0 };
+        let normal = Vec2 { x: 1.0, y: 0.0 };
+        let angle_after_hit_at = |y: f32, paddle_data: PaddleData| {
+            let mut b = ball(Vec2 { x: 70.0, y }, Vec2 { x: -300.0, y: 0.0 });
+            b.deflect(normal, paddle, paddle_data);
+            assert!((b.velocity.length() - 300.0).abs() < 0.01);
+            b.velocity.y.atan2(b.velocity.x).to_degrees()
+        };
+
+        assert!(angle_after_hit_at(300.0, still).abs() < 0.01);
+        // Halfway from the middle to the very end, which is the radius past the corner
+        let reach = 25.0 + RADIUS;
+        assert!((angle_after_hit_at(300.0 + reach / 2.0, still) - 30.0).abs() < 0.01);
+        assert!((angle_after_hit_at(300.0 - reach, still) + 60.0).abs() < 0.01);
+        assert!((angle_after_hit_at(300.0 + reach * 2.0, still) - 60.0).abs() < 0.01);
+
+        // A paddle moving down drags the ball down with it, but never past the steepest angle
+        let moving = PaddleData {
+            velocity: Vec2 { x: 0.0, y: 150.0 },
+            ..still
+        };
+        assert!(angle_after_hit_at(300.0, moving) > 5.0);
+        assert!((angle_after_hit_at(300.0 + reach, moving) - 60.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn returns_speed_the_ball_up_to_the_maximum() {
+        let info = field();
+        let mut tuning = BallTuning {
+            max_speed: 40_000.0,
+            paddle_speed_up: 1.1,
+            rally_speed_up: 100.0,
+            ..BallTuning::default()
+        };
+        let towards_left = Vec2 {
+            x: -30_000.0,
+            y: 0.0,
+        };
+        let mut b = tuned_ball(Vec2 { x: 400.0, y: 300.0 }, towards_left, tuning);
+        b.fixed_update(&info);
+        assert!((b.velocity.length() - 33_100.0).abs() < 1.0);
+
+        let mut b = tuned_ball(Vec2 { x: 400.0, y: 300.0 }, towards_left * 1.5, tuning);
+        b.fixed_update(&info);
+        assert!((b.velocity.length() - 40_000.0).abs() < 1.0);
+
+        // Speeds up over time too, in the open field
+        tuning.speed_up_per_second = 60.0;
+        let mut b = tuned_ball(
+            Vec2 { x: 400.0, y: 300.0 },
+            Vec2 { x: 0.0, y: 100.0 },
+            tuning,
+        );
+        b.fixed_update(&info);
+        assert!((b.velocity.length() - 101.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn rally_speed_follows_the_exponent() {
+        let tuning = BallTuning {
+            rally_speed_up: 10.0,
+            rally_exponent: 0.5,
+            ..BallTuning::default()
+        };
+        assert_eq!(tuning.rally_speed(0), 0.0);
+        assert_eq!(tuning.rally_speed(4), 20.0);
+        assert_eq!(tuning.rally_speed(16), 40.0);
+    }
+
+    #[test]
+    fn only_the_ball_that_scores_leaves_the_field() {
+        let mut info = field();
+        info.add(Box::new(Scoreboard::new(
+            &Vec2 { x: 400.0, y: 50.0 },
+            false,
+        )));
+        let towards_left_goal = Vec2 { x: -3000.0, y: 0.0 };
+        let scoring = info.add(Box::new(ball(
+            Vec2 { x: 60.0, y: 100.0 },
+            towards_left_goal,
+        )));
+        let other = info.add(Box::new(ball(
+            Vec2 { x: 400.0, y: 300.0 },
+            Vec2 { x: 100.0, y: 0.0 },
+        )));
+        let update = |info: &mut UpdateInfo, id: ActorId| {
+            info.current = Option::Some(id);
+            info.get(id).unwrap().borrow_mut().fixed_update(info);
+            info.apply_commands();
+            let points = info
+                .events
+                .get_mut()
+                .drain(..)
+                .filter(|e| {
+                    matches!(
+                        e,
+                        GameEvent::PointScored {
+                            scorer: Side::Right,
+                            ..
+                        }
+                    )
+                })
+                .count();
+            assert_eq!(points, 1);
+        };
+
+        update(&mut info, scoring);
+        assert!(info.get(scoring).is_none());
+        assert_eq!(info.get_as::<Ball>(other).unwrap().phase, Phase::InPlay);
+
+        // The last ball stays to be served again
+        info.get_as_mut::<Ball>(other).unwrap().position = Vec2 { x: 60.0, y: 100.0 };
+        info.get_as_mut::<Ball>(other).unwrap().velocity = towards_left_goal;
+        update(&mut info, other);
+        assert!(matches!(
+            info.get_as::<Ball>(other).unwrap().phase,
+            Phase::Waiting { .. }
+        ));
+        assert_eq!(info.all::<Ball>().count(), 1);
+    }
 }
@@ -0,0 +1,84 @@
+use crate::actor::*;
+use crate::renderer::Renderer;
+use crate::world::GameSettings;
+
+use super::ball::{self, Ball};
+
+/// Serves a new ball every `spawn_interval` seconds of play, up to `max_balls`.
+pub struct BallSpawner {
+    position: Vec2,
+    settings: GameSettings,
+    spawn_in: f32,
+}
+
+impl BallSpawner {
+    pub fn new(position: &Vec2, settings: &GameSettings) -> BallSpawner {
+        BallSpawner {
+            position: *position,
+            settings: *settings,
+            spawn_in: settings.multi_ball.spawn_interval,
+        }
+    }
+}
+
+impl Actor for BallSpawner {
+    fn position(&self) -> &Vec2 {
+        &self.position
+    }
+
+    fn set_position(&mut self, new_pos: &Vec2) {
+        self.position = *new_pos;
+    }
+
+    fn update(&mut self, _info: &UpdateInfo) {}
+
+    fn fixed_update(&mut self, info: &UpdateInfo) {
+        let interval = self.settings.multi_ball.spawn_interval;
+        if interval <= 0.0 {
+            return;
+        }
+
+        // The clock only runs during rallies
+        if !info.all::<Ball>().any(|b| b.in_play()) {
+            return;
+        }
+
+        self.spawn_in -= info.elapsed_sec_f32;
+        if self.spawn_in > 0.0 {
+            return;
+        }
+        self.spawn_in += interval;
+
+        if ball::count_balls(info) < self.settings.multi_ball.max_balls {
+            let ball = Ball::new_served(&self.position, &self.settings, &mut info.rng.borrow_mut());
+            info.emit(ball.serve_event());
+            info.spawn(Box::new(ball));
+        }
+    }
+
+    fn draw(&self, _renderer: &mut dyn Renderer) -> Result<(), String> {
+        Result::Ok(())
+    }
+
+    fn get_collider(&self) -> Option<Collider> {
+        Option::None
+    }
+
+    fn get_data(&self) -> Option<ActorData> {
+        Option::None
+    }
+
+    fn set_data(&mut self, _data: ActorData) {}
+
+    fn save_state(&self) -> Option<ActorState> {
+        Option::Some(ActorState::BallSpawner {
+            spawn_in: self.spawn_in,
+        })
+    }
+
+    fn load_state(&mut self, state: &ActorState) {
+        if let ActorState::BallSpawner { spawn_in } = state {
+            self.spawn_in = *spawn_in;
+        }
+    }
+}
@@ -1,4 +1,5 @@
 pub mod ball;
+pub mod ball_spawner;
 pub mod player_paddle;
 pub mod scoreboard;
 pub mod wall;
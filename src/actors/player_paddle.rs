@@ -1,24 +1,43 @@
-use sdl2::{keyboard::Keycode, pixels::Color, rect::Rect};
+use crate::{
+    actor::*,
+    collision_helper,
+    controller::{Controller, ControllerState},
+    renderer::{Color, Renderer},
+};
 
-use crate::{actor::*, collision_helper};
+pub const MOVE_SPEED: f32 = 150.0;
 
-const MOVE_SPEED: f32 = 150.0;
+#[derive(Copy, Clone)]
+pub struct PaddleData {
+    /// How fast the paddle moved during the last fixed update
+    pub velocity: Vec2,
+    pub size: RectangleDefinition,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PaddleState {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub controller: Option<ControllerState>,
+}
 
 pub struct PlayerPaddle {
     position: Vec2,
+    velocity: Vec2,
     collider: RectangleDefinition,
-    main_player: bool,
+    controller: Box<dyn Controller>,
 }
 
 impl PlayerPaddle {
-    pub fn new(position: &Vec2, main_player: bool) -> PlayerPaddle {
+    pub fn new(position: &Vec2, controller: Box<dyn Controller>) -> PlayerPaddle {
         PlayerPaddle {
             position: *position,
+            velocity: Vec2 { x: 0.0, y: 0.0 },
             collider: RectangleDefinition {
                 width: 15.0,
                 height: 50.0,
             },
-            main_player,
+            controller,
         }
     }
 }
@@ -32,20 +51,17 @@ impl Actor for PlayerPaddle {
         self.position = *new_pos;
     }
 
-    fn update(&mut self, info: &UpdateInfo) {
-        let mut moved = false;
-        if (self.main_player && info.keys_pressed.contains(&Keycode::W))
-            || (!self.main_player && info.keys_pressed.contains(&Keycode::Up))
-        {
-            self.position.y -= MOVE_SPEED * info.elapsed_sec_f32;
-            moved = true;
-        }
-        if (self.main_player && info.keys_pressed.contains(&Keycode::S))
-            || (!self.main_player && info.keys_pressed.contains(&Keycode::Down))
-        {
-            self.position.y += MOVE_SPEED * info.elapsed_sec_f32;
-            moved = true;
-        }
+    fn update(&mut self, _info: &UpdateInfo) {}
+
+    fn fixed_update(&mut self, info: &UpdateInfo) {
+        let intent = self
+            .controller
+            .intent(&self.position, &self.collider, info)
+            .clamp(-1.0, 1.0);
+        let start_y = self.position.y;
+        let dy = intent * MOVE_SPEED * info.elapsed_sec_f32;
+        self.position.y += dy;
+        let moved = dy != 0.0;
 
         if moved {
             let my_col = self
@@ -73,11 +89,14 @@ impl Actor for PlayerPaddle {
                 i += 1;
             }
         }
-    }
 
-    fn fixed_update(&mut self, _info: &UpdateInfo) {}
+        self.velocity = Vec2 {
+            x: 0.0,
+            y: (self.position.y - start_y) / info.elapsed_sec_f32,
+        };
+    }
 
-    fn draw(&self, canvas: &mut sdl2::render::Canvas<sdl2::video::Window>) -> Result<(), String> {
+    fn draw(&self, renderer: &mut dyn Renderer) -> Result<(), String> {
         if let ColliderBounds::Rectangle {
             up,
             down: _,
@@ -86,14 +105,13 @@ impl Actor for PlayerPaddle {
             center: _,
         } = ColliderBounds::from(&ColliderType::Rectangle(self.collider), &self.position)
         {
-            canvas.set_draw_color(Color::RGB(255, 255, 255));
-            canvas.fill_rect(Rect::new(
+            renderer.fill_rect(
                 left.round() as i32,
                 up.round() as i32,
                 self.collider.width.round() as u32,
                 self.collider.height.round() as u32,
-            ))?;
-            Result::Ok(())
+                Color::WHITE,
+            )
         } else {
             Result::Err("PlayerPaddle: self.collider is not a Rectangle".to_string())
         }
@@ -107,8 +125,33 @@ impl Actor for PlayerPaddle {
     }
 
     fn get_data(&self) -> Option<ActorData> {
-        Option::None
+        Option::Some(ActorData::Paddle(PaddleData {
+            velocity: self.velocity,
+            size: self.collider,
+        }))
     }
 
     fn set_data(&mut self, _data: ActorData) {}
+
+    fn has_tag(&self, tag: Tag) -> bool {
+        tag == Tag::Paddle
+    }
+
+    fn save_state(&self) -> Option<ActorState> {
+        Option::Some(ActorState::Paddle(PaddleState {
+            position: self.position,
+            velocity: self.velocity,
+            controller: self.controller.save_state(),
+        }))
+    }
+
+    fn load_state(&mut self, state: &ActorState) {
+        if let ActorState::Paddle(s) = state {
+            self.position = s.position;
+            self.velocity = s.velocity;
+            if let Option::Some(c) = &s.controller {
+                self.controller.load_state(c);
+            }
+        }
+    }
 }
@@ -1,30 +1,58 @@
-use sdl2::{pixels::Color, render::TextureQuery, ttf::Font};
-
 use crate::actor::*;
+use crate::event::GameEvent;
+use crate::renderer::{Color, Renderer};
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct ScoreboardData {
+    /// Points in the current game
     pub left_score: u32,
     pub right_score: u32,
+    pub left_games: u32,
+    pub right_games: u32,
+    /// Points played over the whole match
+    pub points_played: u32,
+}
+
+impl ScoreboardData {
+    pub fn new() -> ScoreboardData {
+        ScoreboardData {
+            left_score: 0,
+            right_score: 0,
+            left_games: 0,
+            right_games: 0,
+            points_played: 0,
+        }
+    }
 }
 
-pub struct Scoreboard<'a> {
+impl Default for ScoreboardData {
+    fn default() -> Self {
+        ScoreboardData::new()
+    }
+}
+
+pub struct Scoreboard {
     position: Vec2,
     data: ScoreboardData,
-    font: &'a Font<'a, 'a>,
+    show_games: bool,
 }
 
-impl<'a> Scoreboard<'a> {
-    pub fn new(position: &Vec2, font: &'a Font<'a, 'a>) -> Scoreboard<'a> {
+impl Scoreboard {
+    /// `show_games` adds the games each side has won, for matches of more than one game.
+    pub fn new(position: &Vec2, show_games: bool) -> Scoreboard {
         Scoreboard {
             position: *position,
-            data: ScoreboardData { left_score: 0, right_score: 0 },
-            font,
+            data: ScoreboardData::new(),
+            show_games,
         }
     }
+
+    pub fn data(&self) -> ScoreboardData {
+        self.data
+    }
 }
 
-impl<'a> Actor for Scoreboard<'a> {
+impl Actor for Scoreboard {
     fn position(&self) -> &Vec2 {
         &self.position
     }
@@ -37,27 +65,24 @@ impl<'a> Actor for Scoreboard<'a> {
 
     fn fixed_update(&mut self, _info: &UpdateInfo) {}
 
-    fn draw(&self, canvas: &mut sdl2::render::Canvas<sdl2::video::Window>) -> Result<(), String> {
-        let texture_creator = canvas.texture_creator();
-        let surface = self
-            .font
-            .render(&format!("{} : {}", self.data.left_score, self.data.right_score))
-            .blended(Color::RGB(255, 255, 255))
-            .map_err(|e| e.to_string())?;
-        let tex = texture_creator
-            .create_texture_from_surface(surface)
-            .map_err(|e| e.to_string())?;
-
-        let TextureQuery { width, height, .. } = tex.query();
-        let bounds = sdl2::rect::Rect::new(
-            (self.position.x - width as f32 / 2.0).round() as i32,
-            (self.position.y - height as f32 / 2.0).round() as i32,
-            width,
-            height,
-        );
-
-        canvas.copy(&tex, None, bounds)?;
-        Result::Ok(())
+    fn draw(&self, renderer: &mut dyn Renderer) -> Result<(), String> {
+        let text = if self.show_games {
+            format!(
+                "({}) {} : {} ({})",
+                self.data.left_games,
+                self.data.left_score,
+                self.data.right_score,
+                self.data.right_games
+            )
+        } else {
+            format!("{} : {}", self.data.left_score, self.data.right_score)
+        };
+        renderer.draw_text(
+            &text,
+            self.position.x.round() as i32,
+            self.position.y.round() as i32,
+            Color::WHITE,
+        )
     }
 
     fn get_collider(&self) -> Option<Collider> {
@@ -76,4 +101,27 @@ impl<'a> Actor for Scoreboard<'a> {
             _ => {}
         }
     }
+
+    fn has_tag(&self, tag: Tag) -> bool {
+        tag == Tag::Scoreboard
+    }
+
+    fn save_state(&self) -> Option<ActorState> {
+        Option::Some(ActorState::Scoreboard(self.data))
+    }
+
+    fn load_state(&mut self, state: &ActorState) {
+        if let ActorState::Scoreboard(data) = state {
+            self.data = *data;
+        }
+    }
+
+    fn on_event(&mut self, event: &GameEvent, _info: &UpdateInfo) {
+        match event {
+            GameEvent::PointScored { scores, .. } | GameEvent::MatchWon { scores, .. } => {
+                self.data = *scores;
+            }
+            _ => {}
+        }
+    }
 }
@@ -1,4 +1,5 @@
 use crate::actor::*;
+use crate::renderer::Renderer;
 
 #[derive(Copy, Clone)]
 pub enum WallType {
@@ -21,6 +22,10 @@ impl Wall {
             wall_type,
         }
     }
+
+    pub fn wall_type(&self) -> WallType {
+        self.wall_type
+    }
 }
 
 impl Actor for Wall {
@@ -36,7 +41,7 @@ impl Actor for Wall {
 
     fn fixed_update(&mut self, _info: &UpdateInfo) {}
 
-    fn draw(&self, _canvas: &mut sdl2::render::Canvas<sdl2::video::Window>) -> Result<(), String> {
+    fn draw(&self, _renderer: &mut dyn Renderer) -> Result<(), String> {
         Result::Ok(())
     }
 
@@ -52,4 +57,11 @@ impl Actor for Wall {
     }
 
     fn set_data(&mut self, _data: ActorData) {}
+
+    fn has_tag(&self, tag: Tag) -> bool {
+        match self.wall_type {
+            WallType::Regular => tag == Tag::Wall,
+            WallType::Left | WallType::Right => tag == Tag::Goal,
+        }
+    }
 }
@@ -0,0 +1,550 @@
+use crate::actor::Vec2;
+use crate::event::GameEvent;
+use crate::tuning::BallTuning;
+use crate::world::SCREEN_WIDTH;
+
+/// Samples are interleaved left then right
+pub const CHANNELS: usize = 2;
+
+/// Voices beyond this many cut off the oldest one, so a busy multi-ball rally can't pile up.
+const MAX_VOICES: usize = 16;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Waveform {
+    Square,
+    Triangle,
+}
+
+impl Waveform {
+    /// The wave's value `phase` of the way through a cycle, from -1.0 to 1.0.
+    pub fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        }
+    }
+}
+
+/// How loud a tone is over its life: it rises to full volume over `attack`, falls to
+/// `sustain` over `decay`, and fades out over the last `release` seconds.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Envelope {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Envelope {
+    /// Volume from 0.0 to 1.0 at `time` seconds into a tone that lasts `duration`.
+    pub fn level(&self, time: f32, duration: f32) -> f32 {
+        if time < 0.0 || time >= duration {
+            return 0.0;
+        }
+        let level = if time < self.attack {
+            time / self.attack
+        } else if time < self.attack + self.decay {
+            1.0 - (1.0 - self.sustain) * (time - self.attack) / self.decay
+        } else {
+            self.sustain
+        };
+        let left = duration - time;
+        if left < self.release {
+            level * left / self.release
+        } else {
+            level
+        }
+    }
+}
+
+/// A single note.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Tone {
+    pub waveform: Waveform,
+    /// Hz
+    pub frequency: f32,
+    /// Seconds, including the release
+    pub duration: f32,
+    /// Loudness from 0.0 to 1.0, before the master volume
+    pub gain: f32,
+    pub envelope: Envelope,
+    /// Seconds to wait before the tone starts, for playing notes in sequence
+    pub delay: f32,
+}
+
+/// The sound effects of the game, each a handful of tones.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Sound {
+    PaddleHit,
+    WallBounce,
+    Score,
+    MatchWin,
+}
+
+impl Sound {
+    pub fn tones(self) -> Vec<Tone> {
+        let blip = Envelope {
+            attack: 0.002,
+            decay: 0.02,
+            sustain: 0.6,
+            release: 0.02,
+        };
+        match self {
+            Sound::PaddleHit => vec![tone(Waveform::Square, 480.0, 0.06, 0.0, blip)],
+            Sound::WallBounce => vec![tone(Waveform::Square, 240.0, 0.05, 0.0, blip)],
+            Sound::Score => vec![
+                tone(Waveform::Triangle, 660.0, 0.12, 0.0, blip),
+                tone(Waveform::Triangle, 330.0, 0.25, 0.12, blip),
+            ],
+            Sound::MatchWin => [523.25, 659.25, 783.99, 1046.5]
+                .iter()
+                .enumerate()
+                .map(|(i, f)| {
+                    let last = i == 3;
+                    let duration = if last { 0.5 } else { 0.12 };
+                    tone(Waveform::Triangle, *f, duration, i as f32 * 0.12, blip)
+                })
+                .collect(),
+        }
+    }
+
+    /// The sound an event makes, if any, placed where the ball is.
+    pub fn for_event(event: &GameEvent, tuning: &BallTuning) -> Option<(Sound, VoiceParams)> {
+        match *event {
+            GameEvent::BallHitPaddle { position, velocity } => Option::Some((
+                Sound::PaddleHit,
+                VoiceParams::for_ball(position, velocity, tuning),
+            )),
+            GameEvent::BallHitWall { position, velocity }
+            | GameEvent::BallHitBall { position, velocity } => Option::Some((
+                Sound::WallBounce,
+                VoiceParams::for_ball(position, velocity, tuning),
+            )),
+            GameEvent::PointScored { .. } => Option::Some((Sound::Score, VoiceParams::default())),
+            GameEvent::MatchWon { .. } => Option::Some((Sound::MatchWin, VoiceParams::default())),
+            GameEvent::Serve { .. } => Option::None,
+        }
+    }
+}
+
+/// How a single voice is played on top of its tone.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct VoiceParams {
+    /// From -1.0 for fully left to 1.0 for fully right
+    pub pan: f32,
+    /// Multiplies the frequency, 2.0 is an octave up
+    pub pitch: f32,
+    /// Multiplies the tone's gain
+    pub gain: f32,
+}
+
+impl VoiceParams {
+    /// Pans with the ball across the field and raises the pitch as it speeds up, from
+    /// the tone's own pitch at `initial_speed` to an octave up at `max_speed`.
+    pub fn for_ball(position: Vec2, velocity: Vec2, tuning: &BallTuning) -> VoiceParams {
+        let pan = (position.x / SCREEN_WIDTH as f32 * 2.0 - 1.0).clamp(-1.0, 1.0);
+        let range = tuning.max_speed - tuning.initial_speed;
+        let faster = if range > 0.0 {
+            ((velocity.length() - tuning.initial_speed) / range).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        VoiceParams {
+            pan,
+            pitch: 2.0f32.powf(faster),
+            gain: 1.0,
+        }
+    }
+
+    /// How loud the voice is in the left and right channels. Keeps the same total
+    /// power wherever it is panned.
+    pub fn channel_gains(&self) -> (f32, f32) {
+        let angle = (self.pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+        (angle.cos() * self.gain, angle.sin() * self.gain)
+    }
+}
+
+impl Default for VoiceParams {
+    fn default() -> Self {
+        VoiceParams {
+            pan: 0.0,
+            pitch: 1.0,
+            gain: 1.0,
+        }
+    }
+}
+
+fn tone(waveform: Waveform, frequency: f32, duration: f32, delay: f32, envelope: Envelope) -> Tone {
+    Tone {
+        waveform,
+        frequency,
+        duration,
+        gain: 0.5,
+        envelope,
+        delay,
+    }
+}
+
+/// Master volume and mute, set in the `[audio]` table of the config.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AudioSettings {
+    /// From 0.0 for silent to 1.0 for full volume
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl AudioSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.volume) {
+            return Result::Err("volume must be between 0 and 1".to_string());
+        }
+        Result::Ok(())
+    }
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            volume: 0.5,
+            muted: false,
+        }
+    }
+}
+
+struct Voice {
+    tone: Tone,
+    params: VoiceParams,
+    /// How far through the current cycle the wave is, from 0.0 to 1.0
+    phase: f32,
+    /// Seconds since the tone was played, including its delay
+    age: f32,
+}
+
+/// Synthesizes the playing tones into stereo samples from -1.0 to 1.0. Knows nothing about
+/// any audio device, so its output can be rendered and inspected anywhere.
+pub struct Mixer {
+    sample_rate: f32,
+    settings: AudioSettings,
+    voices: Vec<Voice>,
+}
+
+impl Mixer {
+    pub fn new(sample_rate: i32, settings: AudioSettings) -> Mixer {
+        Mixer {
+            sample_rate: sample_rate as f32,
+            settings,
+            voices: Vec::new(),
+        }
+    }
+
+    pub fn settings(&self) -> AudioSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: AudioSettings) {
+        self.settings = settings;
+    }
+
+    pub fn play(&mut self, sound: Sound, params: VoiceParams) {
+        for t in sound.tones() {
+            self.play_tone(t, params);
+        }
+    }
+
+    pub fn play_tone(&mut self, tone: Tone, params: VoiceParams) {
+        if self.voices.len() >= MAX_VOICES {
+            self.voices.remove(0);
+        }
+        self.voices.push(Voice {
+            tone,
+            params,
+            phase: 0.0,
+            age: 0.0,
+        });
+    }
+
+    /// Number of tones still playing or waiting to start.
+    pub fn active_voices(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// Fills `out` with the next interleaved stereo samples and drops the tones that have
+    /// finished. Muted tones keep playing silently, so unmuting never replays old sounds.
+    pub fn render(&mut self, out: &mut [f32]) {
+        let dt = 1.0 / self.sample_rate;
+        let volume = if self.settings.muted {
+            0.0
+        } else {
+            self.settings.volume
+        };
+
+        for frame in out.chunks_mut(CHANNELS) {
+            let mut left = 0.0;
+            let mut right = 0.0;
+            for v in self.voices.iter_mut() {
+                let time = v.age - v.tone.delay;
+                if time >= 0.0 {
+                    let level = v.tone.envelope.level(time, v.tone.duration);
+                    let sample = v.tone.waveform.sample(v.phase) * level * v.tone.gain;
+                    let (left_gain, right_gain) = v.params.channel_gains();
+                    left += sample * left_gain;
+                    right += sample * right_gain;
+                    v.phase = (v.phase + v.tone.frequency * v.params.pitch * dt).fract();
+                }
+                v.age += dt;
+            }
+            let mixed = [left, right];
+            for (sample, m) in frame.iter_mut().zip(mixed.iter()) {
+                *sample = (m * volume).clamp(-1.0, 1.0);
+            }
+        }
+
+        self.voices
+            .retain(|v| v.age < v.tone.delay + v.tone.duration);
+    }
+
+    /// Renders the next `seconds` of sound into a new buffer instead of a device.
+    pub fn render_offline(&mut self, seconds: f32) -> Vec<f32> {
+        let frames = (seconds * self.sample_rate).round() as usize;
+        let mut out = vec![0.0; frames * CHANNELS];
+        self.render(&mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Eight samples per cycle of a 1000 Hz tone, so every sample lands on a known phase
+    const SAMPLE_RATE: i32 = 8000;
+
+    /// Full volume the whole time
+    const FLAT: Envelope = Envelope {
+        attack: 0.0,
+        decay: 0.0,
+        sustain: 1.0,
+        release: 0.0,
+    };
+
+    fn full_volume() -> AudioSettings {
+        AudioSettings {
+            volume: 1.0,
+            muted: false,
+        }
+    }
+
+    fn flat_tone(waveform: Waveform, duration: f32, delay: f32) -> Tone {
+        Tone {
+            waveform,
+            frequency: 1000.0,
+            duration,
+            gain: 1.0,
+            envelope: FLAT,
+            delay,
+        }
+    }
+
+    /// Every `CHANNELS`th sample, starting at `channel`
+    fn channel(samples: &[f32], channel: usize) -> Vec<f32> {
+        samples
+            .iter()
+            .skip(channel)
+            .step_by(CHANNELS)
+            .copied()
+            .collect()
+    }
+
+    fn assert_close(got: &[f32], want: &[f32]) {
+        assert_eq!(got.len(), want.len());
+        for (g, w) in got.iter().zip(want.iter()) {
+            assert!((g - w).abs() < 1e-4, "got {:?}, want {:?}", got, want);
+        }
+    }
+
+    #[test]
+    fn envelope_rises_decays_sustains_and_releases() {
+        let envelope = Envelope {
+            attack: 0.1,
+            decay: 0.1,
+            sustain: 0.5,
+            release: 0.2,
+        };
+        let levels: Vec<f32> = [-0.1, 0.0, 0.05, 0.1, 0.15, 0.5, 0.9, 1.0]
+            .iter()
+            .map(|t| envelope.level(*t, 1.0))
+            .collect();
+        assert_close(&levels, &[0.0, 0.0, 0.5, 1.0, 0.75, 0.5, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn square_and_triangle_waves_come_out_as_drawn() {
+        // Centered, so each channel gets the same share
+        let share = std::f32::consts::FRAC_1_SQRT_2;
+        let cycle = 8.0 / SAMPLE_RATE as f32;
+
+        let mut mixer = Mixer::new(SAMPLE_RATE, full_volume());
+        mixer.play_tone(
+            flat_tone(Waveform::Square, 1.0, 0.0),
+            VoiceParams::default(),
+        );
+        let out = mixer.render_offline(cycle);
+        let want: Vec<f32> = [1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0]
+            .iter()
+            .map(|s| s * share)
+            .collect();
+        assert_close(&channel(&out, 0), &want);
+        assert_close(&channel(&out, 1), &want);
+
+        let mut mixer = Mixer::new(SAMPLE_RATE, full_volume());
+        mixer.play_tone(
+            flat_tone(Waveform::Triangle, 1.0, 0.0),
+            VoiceParams::default(),
+        );
+        let out = mixer.render_offline(cycle);
+        let want: Vec<f32> = [-1.0, -0.5, 0.0, 0.5, 1.0, 0.5, 0.0, -0.5]
+            .iter()
+            .map(|s| s * share)
+            .collect();
+        assert_close(&channel(&out, 0), &want);
+    }
+
+    #[test]
+    fn volume_scales_and_mute_silences_without_pausing() {
+        let mut settings = full_volume();
+        settings.volume = 0.5;
+        let mut mixer = Mixer::new(SAMPLE_RATE, settings);
+        mixer.play_tone(
+            flat_tone(Waveform::Square, 0.01, 0.0),
+            VoiceParams::default(),
+        );
+        let out = mixer.render_offline(0.001);
+        assert!((out[0] - 0.5 * std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-4);
+
+        settings.muted = true;
+        mixer.set_settings(settings);
+        let out = mixer.render_offline(0.005);
+        assert!(out.iter().all(|s| *s == 0.0));
+
+        // The tone kept going while muted, so it is over just as soon
+        settings.muted = false;
+        mixer.set_settings(settings);
+        mixer.render_offline(0.005);
+        assert_eq!(mixer.active_voices(), 0);
+    }
+
+    #[test]
+    fn voices_wait_for_their_delay_and_end_after_their_duration() {
+        let mut mixer = Mixer::new(SAMPLE_RATE, full_volume());
+        mixer.play_tone(
+            flat_tone(Waveform::Square, 0.1, 0.05),
+            VoiceParams::default(),
+        );
+
+        // Steps around the edges by a few samples, as the voice's age adds up in floats
+        let out = mixer.render_offline(0.045);
+        assert!(out.iter().all(|s| *s == 0.0));
+        mixer.render_offline(0.01);
+        let out = mixer.render_offline(0.09);
+        assert!(out.iter().all(|s| *s != 0.0));
+        assert_eq!(mixer.active_voices(), 1);
+
+        mixer.render_offline(0.01);
+        assert_eq!(mixer.active_voices(), 0);
+        let out = mixer.render_offline(0.01);
+        assert!(out.iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn too_many_voices_cut_off_the_oldest() {
+        let mut mixer = Mixer::new(SAMPLE_RATE, full_volume());
+        for _ in 0..MAX_VOICES + 4 {
+            mixer.play(Sound::PaddleHit, VoiceParams::default());
+        }
+        assert_eq!(mixer.active_voices(), MAX_VOICES);
+    }
+
+    /// Where the ball's sound goes as it crosses the field
+    fn gains_at(x: f32) -> (f32, f32) {
+        let velocity = Vec2 { x: 300.0, y: 0.0 };
+        VoiceParams::for_ball(Vec2 { x, y: 300.0 }, velocity, &BallTuning::default())
+            .channel_gains()
+    }
+
+    #[test]
+    fn ball_sounds_follow_the_ball_across_the_field() {
+        let (left, right) = gains_at(0.0);
+        assert!((left - 1.0).abs() < 1e-4 && right.abs() < 1e-4);
+        let (left, right) = gains_at(SCREEN_WIDTH as f32);
+        assert!(left.abs() < 1e-4 && (right - 1.0).abs() < 1e-4);
+        let (left, right) = gains_at(SCREEN_WIDTH as f32 / 2.0);
+        assert!((left - right).abs() < 1e-4);
+
+        // Louder on the side the ball is on, at the same total power
+        let (left, right) = gains_at(200.0);
+        assert!(left > right);
+        assert!((left.powi(2) + right.powi(2) - 1.0).abs() < 1e-4);
+        // Off the field counts as fully to one side
+        assert_eq!(gains_at(-50.0), gains_at(0.0));
+
+        // Only the left channel gets a hit on the left wall
+        let mut mixer = Mixer::new(SAMPLE_RATE, full_volume());
+        let params = VoiceParams::for_ball(
+            Vec2 { x: 0.0, y: 300.0 },
+            Vec2 { x: 300.0, y: 0.0 },
+            &BallTuning::default(),
+        );
+        mixer.play(Sound::WallBounce, params);
+        let out = mixer.render_offline(0.03);
+        assert!(channel(&out, 0).iter().any(|s| *s != 0.0));
+        assert!(channel(&out, 1).iter().all(|s| s.abs() < 1e-4));
+    }
+
+    /// Pitch of a ball sound at `speed`
+    fn pitch_at(speed: f32) -> f32 {
+        let velocity = Vec2 { x: 0.6, y: 0.8 } * speed;
+        VoiceParams::for_ball(
+            Vec2 { x: 400.0, y: 300.0 },
+            velocity,
+            &BallTuning::default(),
+        )
+        .pitch
+    }
+
+    /// How many times the left channel goes from positive to negative
+    fn falling_crossings(samples: &[f32]) -> usize {
+        let left = channel(samples, 0);
+        left.windows(2)
+            .filter(|w| w[0] >= 0.0 && w[1] < 0.0)
+            .count()
+    }
+
+    #[test]
+    fn ball_sounds_rise_an_octave_by_max_speed() {
+        let tuning = BallTuning::default();
+        assert!((pitch_at(tuning.initial_speed) - 1.0).abs() < 1e-4);
+        assert!((pitch_at(tuning.max_speed) - 2.0).abs() < 1e-4);
+        assert!((pitch_at(tuning.max_speed * 2.0) - 2.0).abs() < 1e-4);
+        let halfway = pitch_at((tuning.initial_speed + tuning.max_speed) / 2.0);
+        assert!((halfway - std::f32::consts::SQRT_2).abs() < 1e-4);
+
+        // Twice as many cycles come out in the same time
+        let mut cycles = Vec::new();
+        for pitch in [1.0, pitch_at(tuning.max_speed)] {
+            let mut mixer = Mixer::new(SAMPLE_RATE, full_volume());
+            let params = VoiceParams {
+                pitch,
+                ..VoiceParams::default()
+            };
+            mixer.play_tone(flat_tone(Waveform::Square, 1.0, 0.0), params);
+            cycles.push(falling_crossings(&mixer.render_offline(0.05)));
+        }
+        assert_eq!(cycles, vec![50, 100]);
+    }
+}
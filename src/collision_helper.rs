@@ -1,5 +1,23 @@
 use crate::actor::{ColliderBounds, Vec2};
 
+/// How two overlapping shapes touch.
+#[derive(Copy, Clone)]
+pub struct Contact {
+    /// Unit vector along which the first shape has to move to stop overlapping the second
+    pub normal: Vec2,
+    /// How far the first shape has to move along `normal`, zero when only touching
+    pub penetration: f32,
+}
+
+/// Where a moving shape first touches another.
+#[derive(Copy, Clone)]
+pub struct SweepHit {
+    /// Fraction of the motion travelled before touching, from 0.0 to 1.0
+    pub time: f32,
+    /// Unit vector pointing from the surface that was hit towards the moving shape
+    pub normal: Vec2,
+}
+
 pub fn collides(c1: ColliderBounds, c2: ColliderBounds) -> bool {
     match c1 {
         ColliderBounds::Circle { radius, center } => match c2 {
@@ -20,7 +38,10 @@ pub fn collides(c1: ColliderBounds, c2: ColliderBounds) -> bool {
                     };
                 radius.powi(2) >= separation.length_squared()
             }
-            ColliderBounds::Circle { radius, center } => unimplemented!(),
+            ColliderBounds::Circle {
+                radius: radius2,
+                center: center2,
+            } => (radius + radius2).powi(2) >= (center - center2).length_squared(),
         },
         ColliderBounds::Rectangle {
             up,
@@ -46,7 +67,7 @@ pub fn collides(c1: ColliderBounds, c2: ColliderBounds) -> bool {
 
 pub fn separation_vec(c1: ColliderBounds, c2: ColliderBounds) -> Vec2 {
     match c1 {
-        ColliderBounds::Circle { radius, center } => match c2 {
+        ColliderBounds::Circle { center, .. } => match c2 {
             ColliderBounds::Rectangle {
                 up,
                 down,
@@ -61,7 +82,19 @@ pub fn separation_vec(c1: ColliderBounds, c2: ColliderBounds) -> Vec2 {
 
                 center - nearest
             }
-            ColliderBounds::Circle { radius, center } => unimplemented!(),
+            ColliderBounds::Circle {
+                radius: radius2,
+                center: center2,
+            } => {
+                // Like a rectangle, the nearest point of a circle containing `center` is `center`
+                let offset = center - center2;
+                let distance = offset.length();
+                if distance <= radius2 {
+                    Vec2 { x: 0.0, y: 0.0 }
+                } else {
+                    offset * (1.0 - radius2 / distance)
+                }
+            }
         },
         ColliderBounds::Rectangle {
             up,
@@ -86,9 +119,9 @@ pub fn separation_vec(c1: ColliderBounds, c2: ColliderBounds) -> Vec2 {
                     y: center2.y.clamp(up, down),
                 };
 
-                return nearest - nearest2;
+                nearest - nearest2
             }
-            ColliderBounds::Circle { radius, center } => separation_vec(c2, c1),
+            ColliderBounds::Circle { .. } => separation_vec(c2, c1) * -1.0,
         },
     }
 }
@@ -113,22 +146,37 @@ pub fn separation(c1: ColliderBounds, c2: ColliderBounds) -> f32 {
                     };
                 separation.length() - radius
             }
-            ColliderBounds::Circle { radius, center } => unimplemented!(),
+            ColliderBounds::Circle {
+                radius: radius2,
+                center: center2,
+            } => (center - center2).length() - radius - radius2,
         },
         ColliderBounds::Rectangle {
             up,
             down,
             left,
             right,
-            center,
+            center: _,
         } => match c2 {
             ColliderBounds::Rectangle {
                 up: up2,
                 down: down2,
                 left: left2,
                 right: right2,
-                center: center2,
-            } => unimplemented!(),
+                center: _,
+            } => {
+                let overlap_x = right.min(right2) - left.max(left2);
+                let overlap_y = down.min(down2) - up.max(up2);
+                if overlap_x >= 0.0 && overlap_y >= 0.0 {
+                    -overlap_x.min(overlap_y)
+                } else {
+                    Vec2 {
+                        x: (-overlap_x).max(0.0),
+                        y: (-overlap_y).max(0.0),
+                    }
+                    .length()
+                }
+            }
             ColliderBounds::Circle {
                 radius: _,
                 center: _,
@@ -136,3 +184,527 @@ pub fn separation(c1: ColliderBounds, c2: ColliderBounds) -> f32 {
         },
     }
 }
+
+/// How `c1` touches `c2`, or `None` if they don't.
+pub fn contact(c1: ColliderBounds, c2: ColliderBounds) -> Option<Contact> {
+    match (c1, c2) {
+        (
+            ColliderBounds::Circle { radius, center },
+            ColliderBounds::Circle {
+                radius: radius2,
+                center: center2,
+            },
+        ) => {
+            let offset = center - center2;
+            let distance = offset.length();
+            let penetration = radius + radius2 - distance;
+            if penetration < 0.0 {
+                return Option::None;
+            }
+            let normal = if distance > 0.0 {
+                offset * (1.0 / distance)
+            } else {
+                // Any direction separates circles with the same center
+                Vec2 { x: 0.0, y: -1.0 }
+            };
+            Option::Some(Contact {
+                normal,
+                penetration,
+            })
+        }
+        (
+            ColliderBounds::Circle { radius, center },
+            ColliderBounds::Rectangle {
+                up,
+                down,
+                left,
+                right,
+                center: _,
+            },
+        ) => {
+            let separation = separation_vec(c1, c2);
+            let distance = separation.length();
+            if distance > 0.0 {
+                if distance > radius {
+                    return Option::None;
+                }
+                return Option::Some(Contact {
+                    normal: separation * (1.0 / distance),
+                    penetration: radius - distance,
+                });
+            }
+
+            // The center is inside the rectangle, so leave through the nearest side
+            let exits = [
+                (center.y - up, Vec2 { x: 0.0, y: -1.0 }),
+                (down - center.y, Vec2 { x: 0.0, y: 1.0 }),
+                (center.x - left, Vec2 { x: -1.0, y: 0.0 }),
+                (right - center.x, Vec2 { x: 1.0, y: 0.0 }),
+            ];
+            let mut nearest = exits[0];
+            for e in exits.iter() {
+                if e.0 < nearest.0 {
+                    nearest = *e;
+                }
+            }
+            Option::Some(Contact {
+                normal: nearest.1,
+                penetration: radius + nearest.0,
+            })
+        }
+        (ColliderBounds::Rectangle { .. }, ColliderBounds::Circle { .. }) => {
+            contact(c2, c1).map(|c| Contact {
+                normal: c.normal * -1.0,
+                penetration: c.penetration,
+            })
+        }
+        (
+            ColliderBounds::Rectangle {
+                up,
+                down,
+                left,
+                right,
+                center,
+            },
+            ColliderBounds::Rectangle {
+                up: up2,
+                down: down2,
+                left: left2,
+                right: right2,
+                center: center2,
+            },
+        ) => {
+            let overlap_x = right.min(right2) - left.max(left2);
+            let overlap_y = down.min(down2) - up.max(up2);
+            if overlap_x < 0.0 || overlap_y < 0.0 {
+                return Option::None;
+            }
+            let away = |from: f32, to: f32| if from < to { -1.0 } else { 1.0 };
+            if overlap_x < overlap_y {
+                Option::Some(Contact {
+                    normal: Vec2 {
+                        x: away(center.x, center2.x),
+                        y: 0.0,
+                    },
+                    penetration: overlap_x,
+                })
+            } else {
+                Option::Some(Contact {
+                    normal: Vec2 {
+                        x: 0.0,
+                        y: away(center.y, center2.y),
+                    },
+                    penetration: overlap_y,
+                })
+            }
+        }
+    }
+}
+
+/// Finds when a circle of `radius` moving from `center` by `motion` first touches `other`,
+/// whatever its shape.
+pub fn sweep_circle(
+    center: Vec2,
+    radius: f32,
+    motion: Vec2,
+    other: ColliderBounds,
+) -> Option<SweepHit> {
+    match other {
+        ColliderBounds::Rectangle { .. } => sweep_circle_rectangle(center, radius, motion, other),
+        ColliderBounds::Circle {
+            radius: radius2,
+            center: center2,
+        } => sweep_circle_circle(center, radius, motion, center2, radius2),
+    }
+}
+
+/// Finds when a circle of `radius` moving from `center` by `motion` first touches the
+/// still circle of `other_radius` around `other_center`. Overlaps count like they do
+/// for `sweep_circle_rectangle`.
+pub fn sweep_circle_circle(
+    center: Vec2,
+    radius: f32,
+    motion: Vec2,
+    other_center: Vec2,
+    other_radius: f32,
+) -> Option<SweepHit> {
+    let circle = ColliderBounds::Circle { radius, center };
+    let other = ColliderBounds::Circle {
+        radius: other_radius,
+        center: other_center,
+    };
+    if let Option::Some(hit) = sweep_overlapping(circle, motion, other) {
+        return hit;
+    }
+    // Same as a point moving at a circle as big as both together
+    sweep_point_circle(center, motion, other_center, radius + other_radius)
+}
+
+/// Finds when a circle of `radius` moving from `center` by `motion` first touches the
+/// rectangle `rect`. A circle that starts out overlapping counts as touching at time
+/// 0.0, unless it is already moving away, so it can never end up on the far side.
+pub fn sweep_circle_rectangle(
+    center: Vec2,
+    radius: f32,
+    motion: Vec2,
+    rect: ColliderBounds,
+) -> Option<SweepHit> {
+    let (up, down, left, right) = match rect {
+        ColliderBounds::Rectangle {
+            up,
+            down,
+            left,
+            right,
+            center: _,
+        } => (up, down, left, right),
+        ColliderBounds::Circle { .. } => return Option::None,
+    };
+
+    let circle = ColliderBounds::Circle { radius, center };
+    if let Option::Some(hit) = sweep_overlapping(circle, motion, rect) {
+        return hit;
+    }
+    let separation = separation_vec(circle, rect);
+
+    // Moving the circle's center is the same as casting a ray at the rectangle grown by
+    // `radius` on every side, with rounded corners
+    let (tx_enter, tx_exit) = slab(center.x, motion.x, left - radius, right + radius)?;
+    let (ty_enter, ty_exit) = slab(center.y, motion.y, up - radius, down + radius)?;
+    let enter = tx_enter.max(ty_enter);
+    let exit = tx_exit.min(ty_exit);
+    if enter > exit || enter > 1.0 || exit < 0.0 {
+        return Option::None;
+    }
+
+    // Starting inside the grown rectangle without overlapping means the circle is either
+    // touching a side or next to a corner
+    let hit = center + motion * enter.max(0.0);
+    let corner_x = if hit.x < left {
+        Option::Some(left)
+    } else if hit.x > right {
+        Option::Some(right)
+    } else {
+        Option::None
+    };
+    let corner_y = if hit.y < up {
+        Option::Some(up)
+    } else if hit.y > down {
+        Option::Some(down)
+    } else {
+        Option::None
+    };
+
+    match (corner_x, corner_y) {
+        (Option::Some(x), Option::Some(y)) => {
+            sweep_point_circle(center, motion, Vec2 { x, y }, radius)
+        }
+        _ if enter < 0.0 => {
+            let normal = separation * (1.0 / separation.length());
+            if motion.dot(normal) >= 0.0 {
+                return Option::None;
+            }
+            Option::Some(SweepHit { time: 0.0, normal })
+        }
+        _ => {
+            let normal = if tx_enter > ty_enter {
+                Vec2 {
+                    x: -motion.x.signum(),
+                    y: 0.0,
+                }
+            } else {
+                Vec2 {
+                    x: 0.0,
+                    y: -motion.y.signum(),
+                }
+            };
+            Option::Some(SweepHit {
+                time: enter,
+                normal,
+            })
+        }
+    }
+}
+
+/// Handles a moving shape that already overlaps `other`: it counts as touching at once,
+/// unless it is moving away. Returns `None` if the shapes don't overlap at the start.
+fn sweep_overlapping(
+    moving: ColliderBounds,
+    motion: Vec2,
+    other: ColliderBounds,
+) -> Option<Option<SweepHit>> {
+    let contact = contact(moving, other)?;
+    if contact.penetration <= 0.0 {
+        return Option::None;
+    }
+    if motion.dot(contact.normal) >= 0.0 {
+        return Option::Some(Option::None);
+    }
+    Option::Some(Option::Some(SweepHit {
+        time: 0.0,
+        normal: contact.normal,
+    }))
+}
+
+/// The times a point moving from `start` by `motion` is between `min` and `max` along one axis.
+fn slab(start: f32, motion: f32, min: f32, max: f32) -> Option<(f32, f32)> {
+    if motion == 0.0 {
+        if start < min || start > max {
+            return Option::None;
+        }
+        return Option::Some((f32::NEG_INFINITY, f32::INFINITY));
+    }
+    let t1 = (min - start) / motion;
+    let t2 = (max - start) / motion;
+    Option::Some((t1.min(t2), t1.max(t2)))
+}
+
+/// When a point moving from `start` by `motion` first reaches the edge of the circle
+/// around `center`, for a point that starts outside it.
+fn sweep_point_circle(start: Vec2, motion: Vec2, center: Vec2, radius: f32) -> Option<SweepHit> {
+    let offset = start - center;
+    let a = motion.length_squared();
+    let b = 2.0 * motion.dot(offset);
+    let c = offset.length_squared() - radius.powi(2);
+    let discriminant = b.powi(2) - 4.0 * a * c;
+    if a == 0.0 || discriminant < 0.0 {
+        return Option::None;
+    }
+
+    let time = (-b - discriminant.sqrt()) / (2.0 * a);
+    if !(0.0..=1.0).contains(&time) {
+        return Option::None;
+    }
+    let normal = start + motion * time - center;
+    Option::Some(SweepHit {
+        time,
+        normal: normal * (1.0 / normal.length()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADIUS: f32 = 23.0;
+
+    /// A paddle-sized rectangle around `center`
+    fn paddle(center: Vec2) -> ColliderBounds {
+        ColliderBounds::Rectangle {
+            up: center.y - 25.0,
+            down: center.y + 25.0,
+            left: center.x - 7.5,
+            right: center.x + 7.5,
+            center,
+        }
+    }
+
+    /// A ball-sized circle around `center`
+    fn circle(center: Vec2) -> ColliderBounds {
+        ColliderBounds::Circle {
+            radius: RADIUS,
+            center,
+        }
+    }
+
+    fn vec2(x: f32, y: f32) -> Vec2 {
+        Vec2 { x, y }
+    }
+
+    #[test]
+    fn very_fast_ball_stops_at_the_paddle() {
+        let rect = paddle(vec2(40.0, 300.0));
+        let hit = sweep_circle(vec2(400.0, 300.0), RADIUS, vec2(-100_000.0, 0.0), rect)
+            .expect("ball went through the paddle");
+        let touching = 400.0 - (40.0 + 7.5 + RADIUS);
+        assert!((hit.time * 100_000.0 - touching).abs() < 0.01);
+        assert_eq!(hit.normal, vec2(1.0, 0.0));
+    }
+
+    #[test]
+    fn no_path_that_overlaps_the_paddle_is_missed() {
+        let rect = paddle(vec2(0.0, 0.0));
+        let start = vec2(200.0, 0.0);
+        for speed in [50.0, 500.0, 5_000.0, 50_000.0] {
+            for step in 0..=200 {
+                let target_y = -100.0 + step as f32;
+                let offset = vec2(-200.0, target_y);
+                let motion = offset * (speed / offset.length());
+                let sweep = sweep_circle(start, RADIUS, motion, rect);
+
+                // Walk the path in small steps to find where it first overlaps
+                let steps = (speed as usize).max(1000);
+                let first_overlap = (0..=steps)
+                    .map(|i| i as f32 / steps as f32)
+                    .find(|t| separation_vec(circle(start + motion * *t), rect).length() < RADIUS);
+                match (first_overlap, sweep) {
+                    (Option::Some(t), Option::Some(hit)) => assert!(
+                        hit.time <= t,
+                        "hit at {} after overlapping at {} for speed {} aiming at {}",
+                        hit.time,
+                        t,
+                        speed,
+                        target_y
+                    ),
+                    (Option::Some(t), Option::None) => panic!(
+                        "missed an overlap at {} for speed {} aiming at {}",
+                        t, speed, target_y
+                    ),
+                    (Option::None, _) => {}
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hits_on_the_corner_push_away_from_the_corner() {
+        let rect = paddle(vec2(0.0, 0.0));
+        // Aimed so the center passes the corner at (7.5, -25.0) diagonally, within the radius
+        let hit = sweep_circle(vec2(100.0, -100.0), RADIUS, vec2(-100.0, 100.0), rect)
+            .expect("missed the corner");
+        let center = vec2(100.0, -100.0) + vec2(-100.0, 100.0) * hit.time;
+        let to_corner = center - vec2(7.5, -25.0);
+        assert!((to_corner.length() - RADIUS).abs() < 0.01);
+        assert!((hit.normal - to_corner * (1.0 / RADIUS)).length() < 0.001);
+        assert!(hit.normal.x > 0.0 && hit.normal.y < 0.0);
+    }
+
+    #[test]
+    fn passing_just_outside_the_corner_misses() {
+        let rect = paddle(vec2(0.0, 0.0));
+        // Goes past the corner at (7.5, -25.0) one unit further than the radius, well inside
+        // the rectangle grown by the radius on every side
+        let away = vec2(1.0, -1.0) * (1.0 / std::f32::consts::SQRT_2);
+        let start = vec2(7.5, -25.0) + away * (RADIUS + 1.0) - vec2(50.0, 50.0);
+        assert!(sweep_circle(start, RADIUS, vec2(100.0, 100.0), rect).is_none());
+    }
+
+    #[test]
+    fn starting_inside_the_paddle_hits_at_once_unless_leaving() {
+        let rect = paddle(vec2(0.0, 0.0));
+        let start = vec2(20.0, 0.0);
+
+        let hit = sweep_circle(start, RADIUS, vec2(-10.0, 0.0), rect).expect("went further in");
+        assert_eq!(hit.time, 0.0);
+        assert_eq!(hit.normal, vec2(1.0, 0.0));
+
+        assert!(sweep_circle(start, RADIUS, vec2(10.0, 0.0), rect).is_none());
+    }
+
+    #[test]
+    fn starting_exactly_touching_the_paddle_hits_at_once_unless_leaving() {
+        let rect = paddle(vec2(0.0, 0.0));
+        let start = vec2(7.5 + RADIUS, 0.0);
+
+        let hit = sweep_circle(start, RADIUS, vec2(-10.0, 0.0), rect).expect("went through");
+        assert_eq!(hit.time, 0.0);
+        assert_eq!(hit.normal, vec2(1.0, 0.0));
+
+        assert!(sweep_circle(start, RADIUS, vec2(10.0, 0.0), rect).is_none());
+        assert!(sweep_circle(start, RADIUS, vec2(0.0, 10.0), rect).is_none());
+    }
+
+    #[test]
+    fn very_fast_ball_stops_at_another_ball() {
+        let other = circle(vec2(0.0, 0.0));
+        // Far more than a screen's width in one fixed update
+        let hit = sweep_circle(vec2(1_000.0, 5.0), RADIUS, vec2(-2_000.0, 0.0), other)
+            .expect("went through the other ball");
+        let center = vec2(1_000.0, 5.0) + vec2(-2_000.0, 0.0) * hit.time;
+        assert!((center.length() - 2.0 * RADIUS).abs() < 0.01);
+        assert!((hit.normal - center * (1.0 / center.length())).length() < 0.001);
+    }
+
+    #[test]
+    fn overlapping_balls_only_hit_when_moving_closer() {
+        let other = circle(vec2(0.0, 0.0));
+        let start = vec2(30.0, 0.0);
+        let hit = sweep_circle(start, RADIUS, vec2(-5.0, 0.0), other).expect("went further in");
+        assert_eq!(hit.time, 0.0);
+        assert_eq!(hit.normal, vec2(1.0, 0.0));
+        assert!(sweep_circle(start, RADIUS, vec2(5.0, 0.0), other).is_none());
+    }
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        (a - b).length() < 0.0001
+    }
+
+    #[test]
+    fn overlapping_circles_push_apart_along_their_centers() {
+        let first = circle(vec2(0.0, 0.0));
+        let second = circle(vec2(18.0, 24.0));
+        assert!(collides(first, second));
+        assert_eq!(separation(first, second), 30.0 - 2.0 * RADIUS);
+
+        let c = contact(first, second).expect("circles overlap");
+        assert!(close(c.normal, vec2(-0.6, -0.8)));
+        assert!((c.penetration - 16.0).abs() < 0.0001);
+        let c = contact(second, first).expect("circles overlap");
+        assert!(close(c.normal, vec2(0.6, 0.8)));
+        // From the edge of the second circle to the center of the first
+        assert!(close(separation_vec(first, second), vec2(-4.2, -5.6)));
+
+        let apart = circle(vec2(30.0, 40.0));
+        assert!(!collides(first, apart));
+        assert!(contact(first, apart).is_none());
+        assert_eq!(separation(first, apart), 50.0 - 2.0 * RADIUS);
+    }
+
+    #[test]
+    fn overlapping_rectangles_push_apart_along_the_smaller_overlap() {
+        let first = paddle(vec2(0.0, 0.0));
+        let second = paddle(vec2(10.0, 5.0));
+        assert!(collides(first, second));
+        assert_eq!(separation(first, second), -5.0);
+        let c = contact(first, second).expect("rectangles overlap");
+        assert_eq!(c.normal, vec2(-1.0, 0.0));
+        assert_eq!(c.penetration, 5.0);
+        let c = contact(second, first).expect("rectangles overlap");
+        assert_eq!(c.normal, vec2(1.0, 0.0));
+
+        // Mostly overlapping sideways, so the short way out is up or down
+        let below = paddle(vec2(2.0, 40.0));
+        let c = contact(first, below).expect("rectangles overlap");
+        assert_eq!(c.normal, vec2(0.0, -1.0));
+        assert_eq!(c.penetration, 10.0);
+        // How far the first has to move to leave the second, like a paddle leaving a wall
+        assert_eq!(separation_vec(first, below).y, -10.0);
+
+        let touching = paddle(vec2(15.0, 0.0));
+        assert!(collides(first, touching));
+        assert_eq!(contact(first, touching).unwrap().penetration, 0.0);
+        let apart = paddle(vec2(20.0, 0.0));
+        assert!(!collides(first, apart));
+        assert!(contact(first, apart).is_none());
+        assert_eq!(separation(first, apart), 5.0);
+    }
+
+    #[test]
+    fn circle_and_rectangle_push_apart_in_opposite_directions() {
+        let rect = paddle(vec2(0.0, 0.0));
+        let ball = circle(vec2(20.0, 0.0));
+        assert!(collides(ball, rect) && collides(rect, ball));
+        assert_eq!(separation(ball, rect), -10.5);
+        assert_eq!(separation(rect, ball), -10.5);
+
+        let c = contact(ball, rect).expect("ball overlaps the paddle");
+        assert_eq!(c.normal, vec2(1.0, 0.0));
+        assert_eq!(c.penetration, 10.5);
+        let c = contact(rect, ball).expect("ball overlaps the paddle");
+        assert_eq!(c.normal, vec2(-1.0, 0.0));
+        assert_eq!(c.penetration, 10.5);
+        assert_eq!(separation_vec(ball, rect), vec2(12.5, 0.0));
+        assert_eq!(separation_vec(rect, ball), vec2(-12.5, 0.0));
+
+        // A center inside the paddle leaves through the nearest side
+        let inside = circle(vec2(5.0, 0.0));
+        let c = contact(inside, rect).expect("ball is inside the paddle");
+        assert_eq!(c.normal, vec2(1.0, 0.0));
+        assert_eq!(c.penetration, RADIUS + 2.5);
+        assert_eq!(contact(rect, inside).unwrap().normal, vec2(-1.0, 0.0));
+
+        let apart = circle(vec2(40.0, 0.0));
+        assert!(!collides(apart, rect) && !collides(rect, apart));
+        assert!(contact(apart, rect).is_none() && contact(rect, apart).is_none());
+        assert_eq!(separation(rect, apart), 32.5 - RADIUS);
+    }
+}
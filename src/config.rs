@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+
+use sdl2::keyboard::Keycode;
+use serde::{Deserialize, Serialize};
+
+use crate::audio::AudioSettings;
+use crate::input::{Action, KeyBindings};
+use crate::rules::{MatchRules, ServeDirection, Server};
+use crate::tuning::{BallTuning, MultiBall};
+
+pub const DEFAULT_CONFIG_PATH: &str = "pong.toml";
+
+/// Player preferences loaded from a TOML file. Anything the file leaves out keeps its default.
+///
+/// ```toml
+/// [keys]
+/// left_up = "W"
+/// left_down = "S"
+/// quit = "Escape"
+///
+/// [rules]
+/// preset = "table_tennis"
+/// points_to_win = 21
+///
+/// [ball]
+/// max_bounce_angle = 45.0
+///
+/// [multi_ball]
+/// max_balls = 3
+///
+/// [audio]
+/// volume = 0.8
+/// ```
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Config {
+    pub key_bindings: KeyBindings,
+    pub rules: MatchRules,
+    pub ball: BallTuning,
+    pub multi_ball: MultiBall,
+    pub audio: AudioSettings,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    /// Action name to SDL key name
+    keys: BTreeMap<String, String>,
+    rules: RulesFile,
+    ball: BallFile,
+    multi_ball: MultiBallFile,
+    audio: AudioFile,
+}
+
+/// Match rules, starting from `preset` and overriding whichever fields are given
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RulesFile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preset: Option<String>,
+    points_to_win: Option<u32>,
+    win_by: Option<u32>,
+    best_of: Option<u32>,
+    server: Option<String>,
+    /// Points in a row each player serves, for the `alternate` server
+    serve_every: Option<u32>,
+    serve_direction: Option<String>,
+    /// Seconds
+    serve_delay: Option<f32>,
+}
+
+impl Config {
+    /// Loads the config at `path`, or the defaults if there is no file there.
+    pub fn load(path: &str) -> Result<Config, String> {
+        let text = match fs::read_to_string(path) {
+            Result::Ok(t) => t,
+            Result::Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Result::Ok(Config::default())
+            }
+            Result::Err(e) => {
+                return Result::Err(format!("Failed to read config at {}: {}", path, e))
+            }
+        };
+        Config::from_toml(&text).map_err(|e| format!("Invalid config at {}: {}", path, e))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Config, String> {
+        let file: ConfigFile = toml::from_str(text).map_err(|e| e.to_string())?;
+
+        let mut config = Config::default();
+        for (action_name, key_name) in file.keys.iter() {
+            let action = Action::from_name(action_name)
+                .ok_or(format!("Unknown action {} in [keys]", action_name))?;
+            let key = Keycode::from_name(key_name)
+                .ok_or(format!("Unknown key {} for {}", key_name, action_name))?;
+            config.key_bindings.rebind(action, key);
+        }
+        config.rules = rules_from_file(&file.rules)?;
+        config.ball = ball_from_file(&file.ball)?;
+        config.multi_ball = multi_ball_from_file(&file.multi_ball)?;
+        config.audio = audio_from_file(&file.audio)?;
+        Result::Ok(config)
+    }
+
+    pub fn to_toml(&self) -> Result<String, String> {
+        let mut file = ConfigFile::default();
+        for action in Action::ALL.iter() {
+            if let Option::Some(key) = self.key_bindings.key(*action) {
+                file.keys.insert(action.name().to_string(), key.name());
+            }
+        }
+
+        let rules = &self.rules;
+        file.rules = RulesFile {
+            preset: Option::None,
+            points_to_win: Option::Some(rules.points_to_win),
+            win_by: Option::Some(rules.win_by),
+            best_of: Option::Some(rules.best_of),
+            server: Option::Some(rules.server.name().to_string()),
+            serve_every: match rules.server {
+                Server::Alternate { every } => Option::Some(every),
+                _ => Option::None,
+            },
+            serve_direction: Option::Some(rules.serve_direction.name().to_string()),
+            serve_delay: Option::Some(rules.serve_delay),
+        };
+        file.ball = BallFile {
+            max_bounce_angle: Option::Some(self.ball.max_bounce_angle),
+            paddle_influence: Option::Some(self.ball.paddle_influence),
+            initial_speed: Option::Some(self.ball.initial_speed),
+            max_speed: Option::Some(self.ball.max_speed),
+            paddle_speed_up: Option::Some(self.ball.paddle_speed_up),
+            wall_speed_up: Option::Some(self.ball.wall_speed_up),
+            rally_speed_up: Option::Some(self.ball.rally_speed_up),
+            rally_exponent: Option::Some(self.ball.rally_exponent),
+            speed_up_per_second: Option::Some(self.ball.speed_up_per_second),
+        };
+        file.multi_ball = MultiBallFile {
+            max_balls: Option::Some(self.multi_ball.max_balls),
+            spawn_interval: Option::Some(self.multi_ball.spawn_interval),
+            spawn_every_returns: Option::Some(self.multi_ball.spawn_every_returns),
+        };
+        file.audio = AudioFile {
+            volume: Option::Some(self.audio.volume),
+            muted: Option::Some(self.audio.muted),
+        };
+        toml::to_string(&file).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_toml()?)
+            .map_err(|e| format!("Failed to write config to {}: {}", path, e))
+    }
+}
+
+/// Ball tuning, overriding whichever fields are given
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct BallFile {
+    /// Degrees
+    max_bounce_angle: Option<f32>,
+    paddle_influence: Option<f32>,
+    /// Pixels per second
+    initial_speed: Option<f32>,
+    max_speed: Option<f32>,
+    paddle_speed_up: Option<f32>,
+    wall_speed_up: Option<f32>,
+    rally_speed_up: Option<f32>,
+    rally_exponent: Option<f32>,
+    speed_up_per_second: Option<f32>,
+}
+
+fn ball_from_file(file: &BallFile) -> Result<BallTuning, String> {
+    let mut ball = BallTuning::default();
+    if let Option::Some(a) = file.max_bounce_angle {
+        ball.max_bounce_angle = a;
+    }
+    if let Option::Some(i) = file.paddle_influence {
+        ball.paddle_influence = i;
+    }
+    if let Option::Some(v) = file.initial_speed {
+        ball.initial_speed = v;
+    }
+    if let Option::Some(v) = file.max_speed {
+        ball.max_speed = v;
+    }
+    if let Option::Some(v) = file.paddle_speed_up {
+        ball.paddle_speed_up = v;
+    }
+    if let Option::Some(v) = file.wall_speed_up {
+        ball.wall_speed_up = v;
+    }
+    if let Option::Some(v) = file.rally_speed_up {
+        ball.rally_speed_up = v;
+    }
+    if let Option::Some(v) = file.rally_exponent {
+        ball.rally_exponent = v;
+    }
+    if let Option::Some(v) = file.speed_up_per_second {
+        ball.speed_up_per_second = v;
+    }
+
+    ball.validate()
+        .map_err(|e| format!("Invalid [ball]: {}", e))?;
+    Result::Ok(ball)
+}
+
+/// Multi-ball settings, overriding whichever fields are given
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct MultiBallFile {
+    max_balls: Option<u32>,
+    /// Seconds
+    spawn_interval: Option<f32>,
+    spawn_every_returns: Option<u32>,
+}
+
+fn multi_ball_from_file(file: &MultiBallFile) -> Result<MultiBall, String> {
+    let mut multi_ball = MultiBall::default();
+    if let Option::Some(n) = file.max_balls {
+        multi_ball.max_balls = n;
+    }
+    if let Option::Some(s) = file.spawn_interval {
+        multi_ball.spawn_interval = s;
+    }
+    if let Option::Some(n) = file.spawn_every_returns {
+        multi_ball.spawn_every_returns = n;
+    }
+
+    multi_ball
+        .validate()
+        .map_err(|e| format!("Invalid [multi_ball]: {}", e))?;
+    Result::Ok(multi_ball)
+}
+
+/// Sound settings, overriding whichever fields are given
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct AudioFile {
+    /// From 0.0 to 1.0
+    volume: Option<f32>,
+    muted: Option<bool>,
+}
+
+fn audio_from_file(file: &AudioFile) -> Result<AudioSettings, String> {
+    let mut audio = AudioSettings::default();
+    if let Option::Some(v) = file.volume {
+        audio.volume = v;
+    }
+    if let Option::Some(m) = file.muted {
+        audio.muted = m;
+    }
+
+    audio
+        .validate()
+        .map_err(|e| format!("Invalid [audio]: {}", e))?;
+    Result::Ok(audio)
+}
+
+fn rules_from_file(file: &RulesFile) -> Result<MatchRules, String> {
+    let mut rules = match &file.preset {
+        Option::Some(name) => MatchRules::preset(name).ok_or(format!(
+            "Unknown rules preset {}, expected classic or table_tennis",
+            name
+        ))?,
+        Option::None => MatchRules::default(),
+    };
+
+    if let Option::Some(p) = file.points_to_win {
+        rules.points_to_win = p;
+    }
+    if let Option::Some(w) = file.win_by {
+        rules.win_by = w;
+    }
+    if let Option::Some(b) = file.best_of {
+        rules.best_of = b;
+    }
+    if let Option::Some(name) = &file.server {
+        let every = match rules.server {
+            Server::Alternate { every } => every,
+            _ => 1,
+        };
+        rules.server = Server::from_name(name, every).ok_or(format!(
+            "Unknown server {}, expected random, alternate, point_winner or point_loser",
+            name
+        ))?;
+    }
+    if let (Option::Some(e), Server::Alternate { .. }) = (file.serve_every, rules.server) {
+        rules.server = Server::Alternate { every: e };
+    }
+    if let Option::Some(name) = &file.serve_direction {
+        rules.serve_direction = ServeDirection::from_name(name).ok_or(format!(
+            "Unknown serve direction {}, expected away_from_server, towards_server or random",
+            name
+        ))?;
+    }
+    if let Option::Some(d) = file.serve_delay {
+        rules.serve_delay = d;
+    }
+
+    rules
+        .validate()
+        .map_err(|e| format!("Invalid [rules]: {}", e))?;
+    Result::Ok(rules)
+}
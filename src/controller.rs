@@ -0,0 +1,24 @@
+use crate::actor::{RectangleDefinition, UpdateInfo, Vec2};
+use crate::controllers::ai::AiState;
+
+/// Decides how a paddle moves. Called once per fixed update with the paddle's
+/// current position and size, it returns how fast the paddle should move as a
+/// fraction of its top speed, from -1.0 (up) to 1.0 (down).
+pub trait Controller {
+    fn intent(&mut self, position: &Vec2, size: &RectangleDefinition, info: &UpdateInfo) -> f32;
+
+    /// What the controller remembers between fixed updates, or None if nothing.
+    fn save_state(&self) -> Option<ControllerState> {
+        Option::None
+    }
+
+    /// Goes back to a state returned by `save_state`.
+    fn load_state(&mut self, _state: &ControllerState) {}
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ControllerState {
+    Ai(AiState),
+    Network { last: f32 },
+    Replay { tick: usize },
+}
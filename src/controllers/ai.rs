@@ -0,0 +1,242 @@
+use rand::Rng;
+
+use crate::actor::*;
+use crate::actors::ball::Ball;
+use crate::actors::player_paddle::MOVE_SPEED;
+use crate::controller::{Controller, ControllerState};
+use crate::world::SCREEN_HEIGHT;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DifficultySettings {
+    /// Seconds between the ball changing course and the paddle reacting to it.
+    pub reaction_time: f32,
+    /// The predicted intercept is off by up to this many pixels in either direction.
+    pub prediction_error: f32,
+    /// Fastest the paddle moves, in pixels per second.
+    pub max_speed: f32,
+}
+
+impl Difficulty {
+    pub fn settings(self) -> DifficultySettings {
+        match self {
+            Difficulty::Easy => DifficultySettings {
+                reaction_time: 0.35,
+                prediction_error: 45.0,
+                max_speed: 90.0,
+            },
+            Difficulty::Medium => DifficultySettings {
+                reaction_time: 0.2,
+                prediction_error: 20.0,
+                max_speed: 125.0,
+            },
+            Difficulty::Hard => DifficultySettings {
+                reaction_time: 0.08,
+                prediction_error: 5.0,
+                max_speed: 150.0,
+            },
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        match name {
+            "easy" => Option::Some(Difficulty::Easy),
+            "medium" => Option::Some(Difficulty::Medium),
+            "hard" => Option::Some(Difficulty::Hard),
+            _ => Option::None,
+        }
+    }
+}
+
+/// Moves a paddle towards where it expects the ball to cross its side of the field.
+pub struct AiController {
+    settings: DifficultySettings,
+    approaching: bool,
+    last_ball_position: Option<Vec2>,
+    reaction_left: f32,
+    target_y: Option<f32>,
+}
+
+/// What an `AiController` remembers between fixed updates.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AiState {
+    pub approaching: bool,
+    pub last_ball_position: Option<Vec2>,
+    pub reaction_left: f32,
+    pub target_y: Option<f32>,
+}
+
+struct BallState {
+    position: Vec2,
+    velocity: Vec2,
+    radius: f32,
+}
+
+impl AiController {
+    pub fn new(difficulty: Difficulty) -> AiController {
+        AiController {
+            settings: difficulty.settings(),
+            approaching: false,
+            last_ball_position: Option::None,
+            reaction_left: 0.0,
+            target_y: Option::None,
+        }
+    }
+}
+
+impl Controller for AiController {
+    fn intent(&mut self, paddle: &Vec2, size: &RectangleDefinition, info: &UpdateInfo) -> f32 {
+        let ball = match find_ball(info, paddle) {
+            Option::Some(b) => b,
+            Option::None => return 0.0,
+        };
+
+        // Re-think whenever the ball turns around or is served again. A ball waiting
+        // to be served isn't approaching anyone yet.
+        let approaching =
+            ball.velocity.x != 0.0 && (ball.position.x < paddle.x) == (ball.velocity.x > 0.0);
+        let served = match self.last_ball_position {
+            Option::Some(last) => (ball.position - last).length() > ball.radius * 2.0,
+            Option::None => true,
+        };
+        self.last_ball_position = Option::Some(ball.position);
+        if approaching != self.approaching || served {
+            self.approaching = approaching;
+            self.reaction_left = self.settings.reaction_time;
+            self.target_y = Option::None;
+        }
+
+        if self.reaction_left > 0.0 {
+            self.reaction_left -= info.elapsed_sec_f32;
+            return 0.0;
+        }
+
+        let (top, bottom) = field_limits(info, ball.radius);
+        let target = match self.target_y {
+            Option::Some(t) => t,
+            Option::None => {
+                let t = if approaching {
+                    let side = if ball.position.x < paddle.x {
+                        -1.0
+                    } else {
+                        1.0
+                    };
+                    let target_x = paddle.x + side * (size.width / 2.0 + ball.radius);
+                    let error = self.settings.prediction_error;
+                    predict_ball_y(ball.position, ball.velocity, target_x, top, bottom)
+                        + info.rng.borrow_mut().gen_range(-error..=error)
+                } else {
+                    (top + bottom) / 2.0
+                };
+                self.target_y = Option::Some(t);
+                t
+            }
+        };
+
+        let max_step = self.settings.max_speed * info.elapsed_sec_f32;
+        let step = (target - paddle.y).clamp(-max_step, max_step);
+        step / (MOVE_SPEED * info.elapsed_sec_f32)
+    }
+
+    fn save_state(&self) -> Option<ControllerState> {
+        Option::Some(ControllerState::Ai(AiState {
+            approaching: self.approaching,
+            last_ball_position: self.last_ball_position,
+            reaction_left: self.reaction_left,
+            target_y: self.target_y,
+        }))
+    }
+
+    fn load_state(&mut self, state: &ControllerState) {
+        if let ControllerState::Ai(s) = state {
+            self.approaching = s.approaching;
+            self.last_ball_position = s.last_ball_position;
+            self.reaction_left = s.reaction_left;
+            self.target_y = s.target_y;
+        }
+    }
+}
+
+/// Where the ball will be vertically once it reaches `target_x`, bouncing between
+/// `top` and `bottom`, which are the highest and lowest the ball's center can go.
+pub fn predict_ball_y(position: Vec2, velocity: Vec2, target_x: f32, top: f32, bottom: f32) -> f32 {
+    if velocity.x == 0.0 {
+        return position.y;
+    }
+    let t = ((target_x - position.x) / velocity.x).max(0.0);
+    let y = position.y + velocity.y * t;
+
+    let height = bottom - top;
+    if height <= 0.0 {
+        return (top + bottom) / 2.0;
+    }
+    let folded = (y - top).rem_euclid(2.0 * height);
+    if folded > height {
+        top + 2.0 * height - folded
+    } else {
+        top + folded
+    }
+}
+
+/// The ball that will reach `paddle` first, or the nearest one if none are coming its way.
+fn find_ball(info: &UpdateInfo, paddle: &Vec2) -> Option<BallState> {
+    let mut best: Option<(bool, f32, BallState)> = Option::None;
+    for ball in info.all::<Ball>() {
+        let position = *ball.position();
+        let velocity = ball.velocity();
+        let dx = paddle.x - position.x;
+        let approaching = velocity.x != 0.0 && (dx > 0.0) == (velocity.x > 0.0);
+        let cost = if approaching {
+            dx / velocity.x
+        } else {
+            (*paddle - position).length()
+        };
+        let better = match &best {
+            Option::Some((best_approaching, best_cost, _)) => {
+                (approaching, -cost) > (*best_approaching, -*best_cost)
+            }
+            Option::None => true,
+        };
+        if better {
+            let state = BallState {
+                position,
+                velocity,
+                radius: ball.radius(),
+            };
+            best = Option::Some((approaching, cost, state));
+        }
+    }
+    best.map(|(_, _, b)| b)
+}
+
+// The range the center of a ball of `radius` can move in between the regular walls
+fn field_limits(info: &UpdateInfo, radius: f32) -> (f32, f32) {
+    let mut top = 0.0;
+    let mut bottom = SCREEN_HEIGHT as f32;
+    for (_, wall) in info.tagged(Tag::Wall) {
+        if let Option::Some(col) = wall.get_collider() {
+            if let ColliderBounds::Rectangle { up, down, .. } = col.to_bounds(*wall.position()) {
+                if down <= SCREEN_HEIGHT as f32 / 2.0 {
+                    top = down;
+                } else {
+                    bottom = up;
+                }
+            }
+        }
+    }
+    (top + radius, bottom - radius)
+}
@@ -0,0 +1,57 @@
+use std::collections::HashSet;
+
+use crate::actor::{RectangleDefinition, UpdateInfo, Vec2};
+use crate::controller::Controller;
+use crate::input::{Action, AnalogInput};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// A player at this machine. Moves the paddle at full speed while its up or down
+/// action is held, otherwise as far as the analog input for its side asks.
+pub struct LocalController {
+    side: Side,
+}
+
+impl LocalController {
+    pub fn new(side: Side) -> LocalController {
+        LocalController { side }
+    }
+
+    pub fn left() -> LocalController {
+        LocalController::new(Side::Left)
+    }
+
+    pub fn right() -> LocalController {
+        LocalController::new(Side::Right)
+    }
+}
+
+impl Controller for LocalController {
+    fn intent(&mut self, _position: &Vec2, _size: &RectangleDefinition, info: &UpdateInfo) -> f32 {
+        intent_for(self.side, &info.actions_pressed, info.analog)
+    }
+}
+
+/// How fast the paddle on `side` moves for `actions` held and `analog` input.
+pub fn intent_for(side: Side, actions: &HashSet<Action>, analog: AnalogInput) -> f32 {
+    let (up, down, analog) = match side {
+        Side::Left => (Action::LeftUp, Action::LeftDown, analog.left),
+        Side::Right => (Action::RightUp, Action::RightDown, analog.right),
+    };
+
+    let mut intent = 0.0;
+    if actions.contains(&up) {
+        intent -= 1.0;
+    }
+    if actions.contains(&down) {
+        intent += 1.0;
+    }
+    if intent == 0.0 {
+        intent = analog;
+    }
+    intent
+}
@@ -0,0 +1,4 @@
+pub mod ai;
+pub mod local;
+pub mod network;
+pub mod replay;
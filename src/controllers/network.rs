@@ -0,0 +1,44 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::actor::{RectangleDefinition, UpdateInfo, Vec2};
+use crate::controller::{Controller, ControllerState};
+
+/// Follows intents sent from elsewhere, e.g. a remote player's machine. The paddle
+/// keeps the last intent it received until a newer one arrives.
+pub struct NetworkController {
+    receiver: Receiver<f32>,
+    last: f32,
+}
+
+impl NetworkController {
+    /// Returns the controller and the sender that feeds it.
+    pub fn new() -> (NetworkController, Sender<f32>) {
+        let (sender, receiver) = mpsc::channel();
+        (
+            NetworkController {
+                receiver,
+                last: 0.0,
+            },
+            sender,
+        )
+    }
+}
+
+impl Controller for NetworkController {
+    fn intent(&mut self, _position: &Vec2, _size: &RectangleDefinition, _info: &UpdateInfo) -> f32 {
+        while let Result::Ok(intent) = self.receiver.try_recv() {
+            self.last = intent;
+        }
+        self.last
+    }
+
+    fn save_state(&self) -> Option<ControllerState> {
+        Option::Some(ControllerState::Network { last: self.last })
+    }
+
+    fn load_state(&mut self, state: &ControllerState) {
+        if let ControllerState::Network { last } = state {
+            self.last = *last;
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use crate::actor::{RectangleDefinition, UpdateInfo, Vec2};
+use crate::controller::{Controller, ControllerState};
+
+/// Plays back a list of intents, one per fixed update, then stands still.
+pub struct ReplayController {
+    intents: Vec<f32>,
+    tick: usize,
+}
+
+impl ReplayController {
+    pub fn new(intents: Vec<f32>) -> ReplayController {
+        ReplayController { intents, tick: 0 }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.tick >= self.intents.len()
+    }
+}
+
+impl Controller for ReplayController {
+    fn intent(&mut self, _position: &Vec2, _size: &RectangleDefinition, _info: &UpdateInfo) -> f32 {
+        let intent = self.intents.get(self.tick).copied().unwrap_or(0.0);
+        self.tick += 1;
+        intent
+    }
+
+    fn save_state(&self) -> Option<ControllerState> {
+        Option::Some(ControllerState::Replay { tick: self.tick })
+    }
+
+    fn load_state(&mut self, state: &ControllerState) {
+        if let ControllerState::Replay { tick } = state {
+            self.tick = *tick;
+        }
+    }
+}
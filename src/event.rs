@@ -0,0 +1,37 @@
+use crate::actor::Vec2;
+use crate::actors::scoreboard::ScoreboardData;
+use crate::controllers::local::Side;
+
+/// Something that happened during play, emitted by actors and handed to every actor
+/// after the emitting one's update. Ball events carry the ball's position and new velocity.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GameEvent {
+    /// A ball was served, or spawned already moving in multi-ball
+    Serve {
+        towards: Side,
+        position: Vec2,
+        velocity: Vec2,
+    },
+    BallHitPaddle {
+        position: Vec2,
+        velocity: Vec2,
+    },
+    BallHitWall {
+        position: Vec2,
+        velocity: Vec2,
+    },
+    BallHitBall {
+        position: Vec2,
+        velocity: Vec2,
+    },
+    /// `scores` is the score after the point
+    PointScored {
+        scorer: Side,
+        scores: ScoreboardData,
+    },
+    /// Always follows the `PointScored` that decided the match
+    MatchWon {
+        winner: Side,
+        scores: ScoreboardData,
+    },
+}
@@ -0,0 +1,273 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::controllers::local::Side;
+use crate::event::GameEvent;
+use crate::input::Action;
+use crate::renderer::{Color, Renderer};
+use crate::replay::Replay;
+use crate::world::{World, WorldSnapshot, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GameState {
+    /// Waiting for a player to start the match
+    Title,
+    Playing,
+    /// Frozen until pause is pressed again
+    Paused,
+    /// A point was just scored. Shown while the ball waits to be served again.
+    PointScored {
+        scorer: Side,
+        remaining: Duration,
+    },
+    MatchOver {
+        winner: Side,
+    },
+}
+
+/// A world snapshot along with what the game was showing at the time.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GameSnapshot {
+    pub world: WorldSnapshot,
+    pub state: GameState,
+}
+
+/// Runs matches, switching between the title screen, play, pauses and the result.
+/// The world is only stepped while playing or after a point, but it is drawn in every state.
+pub struct Game {
+    world: World,
+    state: GameState,
+    serve_key_name: String,
+    recordings: Vec<Replay>,
+}
+
+impl Game {
+    pub fn new(world: World) -> Game {
+        Game {
+            world,
+            state: GameState::Title,
+            serve_key_name: Action::Serve.description().to_string(),
+            recordings: Vec::new(),
+        }
+    }
+
+    pub fn state(&self) -> GameState {
+        self.state
+    }
+
+    /// Switches straight to `state`, e.g. to follow a game run somewhere else.
+    pub fn set_state(&mut self, state: GameState) {
+        self.state = state;
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Sets the key shown in prompts like "Press Space to start".
+    pub fn set_serve_key_name(&mut self, name: &str) {
+        self.serve_key_name = name.to_string();
+    }
+
+    /// Leaves the title screen and starts playing.
+    pub fn start(&mut self) {
+        if self.state == GameState::Title {
+            self.state = GameState::Playing;
+        }
+    }
+
+    /// Reacts to a single press of `action`. Returns whether the game should keep running.
+    pub fn press(&mut self, action: Action) -> bool {
+        match (action, self.state) {
+            (Action::Quit, _) => return false,
+            (Action::Pause, GameState::Playing) => self.state = GameState::Paused,
+            (Action::Pause, GameState::Paused) => self.state = GameState::Playing,
+            (Action::Serve, GameState::Title) => self.start(),
+            // A replay only holds a single match
+            (Action::Serve, GameState::MatchOver { .. }) if !self.world.replaying() => {
+                self.restart()
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Advances the current state by a frame that took `elapsed`.
+    pub fn step(&mut self, elapsed: Duration) {
+        match self.state {
+            GameState::Playing => {
+                self.world.step(elapsed);
+                self.handle_events();
+            }
+            GameState::PointScored { scorer, remaining } => {
+                self.world.step(elapsed);
+                self.state = match remaining.checked_sub(elapsed) {
+                    Option::Some(remaining) if !remaining.is_zero() => {
+                        GameState::PointScored { scorer, remaining }
+                    }
+                    _ => GameState::Playing,
+                };
+                // Other balls can still score in multi-ball
+                self.handle_events();
+            }
+            GameState::Title | GameState::Paused | GameState::MatchOver { .. } => {
+                self.world.clear_events();
+            }
+        }
+    }
+
+    /// Draws the world with the text of the current state on top, without presenting it.
+    pub fn draw(&self, renderer: &mut dyn Renderer) -> Result<(), String> {
+        self.world.draw(renderer)?;
+
+        let center_x = SCREEN_WIDTH as i32 / 2;
+        let center_y = SCREEN_HEIGHT as i32 / 2;
+        match self.state {
+            GameState::Title => {
+                renderer.draw_text("PONG", center_x, center_y - 100, Color::WHITE)?;
+                renderer.draw_text(
+                    &format!("Press {} to start", self.serve_key_name),
+                    center_x,
+                    center_y + 100,
+                    Color::WHITE,
+                )?;
+            }
+            GameState::Paused => {
+                renderer.draw_text("Paused", center_x, center_y - 100, Color::WHITE)?;
+            }
+            GameState::PointScored { scorer, .. } => {
+                renderer.draw_text(
+                    &format!("{} scores", player_name(scorer)),
+                    center_x,
+                    center_y - 100,
+                    Color::WHITE,
+                )?;
+            }
+            GameState::MatchOver { winner } => {
+                renderer.draw_text(
+                    &format!("{} wins", player_name(winner)),
+                    center_x,
+                    center_y - 100,
+                    Color::WHITE,
+                )?;
+                if !self.world.replaying() {
+                    renderer.draw_text(
+                        &format!("Press {} to play again", self.serve_key_name),
+                        center_x,
+                        center_y + 100,
+                        Color::WHITE,
+                    )?;
+                }
+            }
+            GameState::Playing => {}
+        }
+        Result::Ok(())
+    }
+
+    pub fn snapshot(&self) -> GameSnapshot {
+        GameSnapshot {
+            world: self.world.snapshot(),
+            state: self.state,
+        }
+    }
+
+    /// Goes back to `snapshot`, even if it was taken during an earlier match. While
+    /// recording, only snapshots of the recorded match up to where it has got can be loaded.
+    pub fn restore(&mut self, snapshot: &GameSnapshot) -> Result<(), String> {
+        let same_match = snapshot.world.seed == self.world.seed()
+            && snapshot.world.settings == *self.world.settings();
+        if same_match {
+            self.world.restore(&snapshot.world)?;
+        } else {
+            // A new world can't start recording partway through its match
+            if self.world.recording() {
+                return Result::Err(
+                    "Can't load a snapshot of a different match while recording".to_string(),
+                );
+            }
+            let mut world = World::new(snapshot.world.seed, snapshot.world.settings);
+            world.restore(&snapshot.world)?;
+            self.world = world;
+        }
+        self.state = snapshot.state;
+        Result::Ok(())
+    }
+
+    /// Goes back to `snapshot` from earlier in this game, even from before a restart, to
+    /// simulate it again. Recordings are cut back to it instead of refusing it.
+    pub fn rewind(&mut self, snapshot: &GameSnapshot) -> Result<(), String> {
+        let same_match = snapshot.world.seed == self.world.seed()
+            && snapshot.world.settings == *self.world.settings();
+        if same_match {
+            self.world.rewind(&snapshot.world)?;
+        } else {
+            let mut world = World::new(snapshot.world.seed, snapshot.world.settings);
+            // The match was recorded up to the restart
+            if self.world.recording() {
+                let replay = self
+                    .recordings
+                    .pop()
+                    .ok_or("No recording of the match before the restart")?;
+                world.resume_recording(replay)?;
+            }
+            world.rewind(&snapshot.world)?;
+            self.world = world;
+        }
+        self.state = snapshot.state;
+        Result::Ok(())
+    }
+
+    /// The recordings of every match played so far, oldest first.
+    pub fn take_recordings(&mut self) -> Vec<Replay> {
+        let mut recordings = std::mem::take(&mut self.recordings);
+        if let Option::Some(r) = self.world.take_recording() {
+            recordings.push(r);
+        }
+        recordings
+    }
+
+    fn handle_events(&mut self) {
+        let serve_delay = self.world.settings().rules.serve_delay;
+        for event in self.world.events() {
+            match *event {
+                GameEvent::PointScored { scorer, .. } => {
+                    self.state = GameState::PointScored {
+                        scorer,
+                        remaining: Duration::from_secs_f32(serve_delay),
+                    }
+                }
+                GameEvent::MatchWon { winner, .. } => {
+                    self.state = GameState::MatchOver { winner };
+                    return;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Starts a new match with the same settings, seeded from the previous one.
+    fn restart(&mut self) {
+        let seed = self.world.update_info.rng.borrow_mut().gen();
+        let mut world = World::new(seed, *self.world.settings());
+        if let Option::Some(r) = self.world.take_recording() {
+            self.recordings.push(r);
+            // A fresh world has not been updated yet, so this cannot fail
+            let _ = world.start_recording();
+        }
+
+        self.world = world;
+        self.state = GameState::Playing;
+    }
+}
+
+fn player_name(side: Side) -> &'static str {
+    match side {
+        Side::Left => "Player 1",
+        Side::Right => "Player 2",
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashSet;
+
+use sdl2::controller::{Axis, Button};
+use sdl2::event::Event;
+
+use crate::input::{Action, AnalogInput};
+
+/// Stick movement smaller than this fraction of the full range is ignored.
+pub const DEFAULT_DEADZONE: f32 = 0.2;
+
+#[derive(Copy, Clone, Default)]
+struct Pad {
+    id: u32,
+    stick: f32,
+    dpad_up: bool,
+    dpad_down: bool,
+    serve: bool,
+}
+
+/// Tracks connected game controllers and turns their state into paddle input.
+/// The first controller drives the left paddle and the second the right one; a
+/// controller connected after one was removed takes over the free paddle.
+///
+/// Only SDL's controller events are needed, so without any controllers, or without
+/// SDL at all, this simply produces no input.
+pub struct Gamepads {
+    // Index 0 is the left paddle, 1 the right
+    pads: [Option<Pad>; 2],
+    deadzone: f32,
+}
+
+impl Gamepads {
+    pub fn new() -> Gamepads {
+        Gamepads {
+            pads: [Option::None, Option::None],
+            deadzone: DEFAULT_DEADZONE,
+        }
+    }
+
+    pub fn set_deadzone(&mut self, deadzone: f32) {
+        self.deadzone = deadzone.clamp(0.0, 0.99);
+    }
+
+    /// Assigns the controller with joystick `id` to the first free paddle.
+    /// Returns whether it got one.
+    pub fn connect(&mut self, id: u32) -> bool {
+        if self.pads.iter().flatten().any(|p| p.id == id) {
+            return true;
+        }
+        match self.pads.iter_mut().find(|p| p.is_none()) {
+            Option::Some(slot) => {
+                *slot = Option::Some(Pad {
+                    id,
+                    ..Pad::default()
+                });
+                true
+            }
+            Option::None => false,
+        }
+    }
+
+    pub fn disconnect(&mut self, id: u32) {
+        for slot in self.pads.iter_mut() {
+            if slot.map(|p| p.id) == Option::Some(id) {
+                *slot = Option::None;
+            }
+        }
+    }
+
+    pub fn connected(&self) -> usize {
+        self.pads.iter().flatten().count()
+    }
+
+    /// Updates controller state from an SDL event. Returns a one-off action the
+    /// event triggers, like pressing Start to pause or A to serve.
+    ///
+    /// `ControllerDeviceAdded` carries a device index rather than a joystick id, so
+    /// whoever opens the controller has to `connect` it with its id instead.
+    pub fn handle_event(&mut self, event: &Event) -> Option<Action> {
+        match event {
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.disconnect(*which);
+            }
+            Event::ControllerAxisMotion {
+                which,
+                axis: Axis::LeftY,
+                value,
+                ..
+            } => {
+                let deadzone = self.deadzone;
+                if let Option::Some(pad) = self.pad_mut(*which) {
+                    pad.stick = apply_deadzone(*value as f32 / i16::MAX as f32, deadzone);
+                }
+            }
+            Event::ControllerButtonDown { which, button, .. } => {
+                if *button == Button::Start {
+                    return Option::Some(Action::Pause);
+                }
+                if *button == Button::Back {
+                    return Option::Some(Action::Quit);
+                }
+                if let Option::Some(pad) = self.pad_mut(*which) {
+                    set_button(pad, *button, true);
+                    if *button == Button::A {
+                        return Option::Some(Action::Serve);
+                    }
+                }
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                if let Option::Some(pad) = self.pad_mut(*which) {
+                    set_button(pad, *button, false);
+                }
+            }
+            _ => {}
+        }
+        Option::None
+    }
+
+    /// Adds the actions held on the controllers' D-pads and buttons to `actions`.
+    pub fn add_actions(&self, actions: &mut HashSet<Action>) {
+        let sides = [
+            (Action::LeftUp, Action::LeftDown),
+            (Action::RightUp, Action::RightDown),
+        ];
+        for (pad, (up, down)) in self.pads.iter().zip(sides.iter()) {
+            if let Option::Some(pad) = pad {
+                if pad.dpad_up {
+                    actions.insert(*up);
+                }
+                if pad.dpad_down {
+                    actions.insert(*down);
+                }
+                if pad.serve {
+                    actions.insert(Action::Serve);
+                }
+            }
+        }
+    }
+
+    pub fn analog(&self) -> AnalogInput {
+        AnalogInput {
+            left: self.pads[0].map(|p| p.stick).unwrap_or(0.0),
+            right: self.pads[1].map(|p| p.stick).unwrap_or(0.0),
+        }
+    }
+
+    fn pad_mut(&mut self, id: u32) -> Option<&mut Pad> {
+        self.pads.iter_mut().flatten().find(|p| p.id == id)
+    }
+}
+
+impl Default for Gamepads {
+    fn default() -> Self {
+        Gamepads::new()
+    }
+}
+
+fn set_button(pad: &mut Pad, button: Button, pressed: bool) {
+    match button {
+        Button::DPadUp => pad.dpad_up = pressed,
+        Button::DPadDown => pad.dpad_down = pressed,
+        Button::A => pad.serve = pressed,
+        _ => {}
+    }
+}
+
+// Zero inside the deadzone, then scaled so the rest of the stick's range still reaches full speed
+fn apply_deadzone(value: f32, deadzone: f32) -> f32 {
+    let value = value.clamp(-1.0, 1.0);
+    if value.abs() < deadzone {
+        0.0
+    } else {
+        value.signum() * (value.abs() - deadzone) / (1.0 - deadzone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stick(which: u32, value: i16) -> Event {
+        Event::ControllerAxisMotion {
+            timestamp: 0,
+            which,
+            axis: Axis::LeftY,
+            value,
+        }
+    }
+
+    fn press(which: u32, button: Button) -> Event {
+        Event::ControllerButtonDown {
+            timestamp: 0,
+            which,
+            button,
+        }
+    }
+
+    fn release(which: u32, button: Button) -> Event {
+        Event::ControllerButtonUp {
+            timestamp: 0,
+            which,
+            button,
+        }
+    }
+
+    fn held(pads: &Gamepads) -> HashSet<Action> {
+        let mut actions = HashSet::new();
+        pads.add_actions(&mut actions);
+        actions
+    }
+
+    #[test]
+    fn stick_ignores_the_deadzone_and_scales_the_rest() {
+        let mut pads = Gamepads::new();
+        pads.connect(7);
+
+        pads.handle_event(&stick(7, i16::MAX / 10));
+        assert_eq!(pads.analog().left, 0.0);
+
+        // Halfway between the deadzone and the end of the stick is half speed
+        pads.handle_event(&stick(7, (i16::MAX as f32 * 0.6) as i16));
+        assert!((pads.analog().left - 0.5).abs() < 1e-3);
+
+        pads.handle_event(&stick(7, i16::MAX));
+        assert_eq!(pads.analog().left, 1.0);
+        pads.handle_event(&stick(7, i16::MIN));
+        assert_eq!(pads.analog().left, -1.0);
+
+        pads.set_deadzone(0.5);
+        pads.handle_event(&stick(7, (i16::MAX as f32 * -0.4) as i16));
+        assert_eq!(pads.analog().left, 0.0);
+        assert_eq!(pads.analog().right, 0.0);
+    }
+
+    #[test]
+    fn controllers_take_the_first_free_paddle() {
+        let mut pads = Gamepads::new();
+        assert!(pads.connect(1));
+        assert!(pads.connect(2));
+        assert!(!pads.connect(3));
+        assert_eq!(pads.connected(), 2);
+
+        pads.handle_event(&Event::ControllerDeviceRemoved {
+            timestamp: 0,
+            which: 1,
+        });
+        assert_eq!(pads.connected(), 1);
+
+        // The left paddle came free, so the new controller plays on the left
+        assert!(pads.connect(3));
+        pads.handle_event(&stick(3, i16::MAX));
+        pads.handle_event(&stick(2, i16::MIN));
+        assert_eq!(pads.analog().left, 1.0);
+        assert_eq!(pads.analog().right, -1.0);
+
+        // Controllers that aren't connected do nothing
+        pads.handle_event(&press(1, Button::DPadUp));
+        assert!(held(&pads).is_empty());
+    }
+
+    #[test]
+    fn buttons_map_to_actions() {
+        let mut pads = Gamepads::new();
+        pads.connect(1);
+        pads.connect(2);
+
+        assert_eq!(pads.handle_event(&press(1, Button::DPadUp)), Option::None);
+        assert_eq!(pads.handle_event(&press(2, Button::DPadDown)), Option::None);
+        let want: HashSet<Action> = [Action::LeftUp, Action::RightDown].into_iter().collect();
+        assert_eq!(held(&pads), want);
+
+        pads.handle_event(&release(1, Button::DPadUp));
+        pads.handle_event(&release(2, Button::DPadDown));
+        assert!(held(&pads).is_empty());
+
+        assert_eq!(
+            pads.handle_event(&press(2, Button::A)),
+            Option::Some(Action::Serve)
+        );
+        assert!(held(&pads).contains(&Action::Serve));
+        pads.handle_event(&release(2, Button::A));
+        assert!(held(&pads).is_empty());
+
+        assert_eq!(
+            pads.handle_event(&press(1, Button::Start)),
+            Option::Some(Action::Pause)
+        );
+        assert_eq!(
+            pads.handle_event(&press(2, Button::Back)),
+            Option::Some(Action::Quit)
+        );
+        assert_eq!(pads.handle_event(&press(1, Button::X)), Option::None);
+        assert!(held(&pads).is_empty());
+    }
+}
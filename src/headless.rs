@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use crate::actors::scoreboard::ScoreboardData;
+use crate::renderer::Renderer;
+use crate::world::{self, GameSettings, World};
+
+/// Runs a match without a window, advancing a simulated clock by one target
+/// frame per step instead of reading `Instant::now()`. Nothing is drawn unless asked.
+pub struct HeadlessRunner {
+    world: World,
+    simulated_time: Duration,
+}
+
+impl HeadlessRunner {
+    pub fn new(seed: u64, settings: GameSettings) -> HeadlessRunner {
+        HeadlessRunner::with_world(World::new(seed, settings))
+    }
+
+    pub fn with_world(world: World) -> HeadlessRunner {
+        HeadlessRunner {
+            world,
+            simulated_time: Duration::from_micros(0),
+        }
+    }
+
+    pub fn frame_time() -> Duration {
+        Duration::from_micros(world::TARGET_FRAMETIME_MICROS as u64)
+    }
+
+    /// Simulates a single frame.
+    pub fn step(&mut self) {
+        let elapsed = HeadlessRunner::frame_time();
+        self.world.step(elapsed);
+        self.simulated_time += elapsed;
+    }
+
+    /// Simulates frames until at least `duration` of game time has passed.
+    pub fn run_for(&mut self, duration: Duration) {
+        let end = self.simulated_time + duration;
+        while self.simulated_time < end {
+            self.step();
+        }
+    }
+
+    pub fn simulated_time(&self) -> Duration {
+        self.simulated_time
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    /// Gives access to the simulated world, e.g. to press keys or inspect actors between steps.
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Draws the current state of the world, e.g. into a `SoftwareRenderer`.
+    pub fn draw(&self, renderer: &mut dyn Renderer) -> Result<(), String> {
+        self.world.draw(renderer)
+    }
+
+    pub fn scores(&self) -> Option<ScoreboardData> {
+        self.world.scores()
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use sdl2::keyboard::Keycode;
+
+/// Everything a player can ask the game to do, independent of which key does it.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    LeftUp,
+    LeftDown,
+    RightUp,
+    RightDown,
+    Pause,
+    Quit,
+    Serve,
+    Mute,
+    QuickSave,
+    QuickLoad,
+}
+
+impl Action {
+    pub const ALL: [Action; 10] = [
+        Action::LeftUp,
+        Action::LeftDown,
+        Action::RightUp,
+        Action::RightDown,
+        Action::Pause,
+        Action::Quit,
+        Action::Serve,
+        Action::Mute,
+        Action::QuickSave,
+        Action::QuickLoad,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::LeftUp => "left_up",
+            Action::LeftDown => "left_down",
+            Action::RightUp => "right_up",
+            Action::RightDown => "right_down",
+            Action::Pause => "pause",
+            Action::Quit => "quit",
+            Action::Serve => "serve",
+            Action::Mute => "mute",
+            Action::QuickSave => "quick_save",
+            Action::QuickLoad => "quick_load",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|a| a.name() == name)
+    }
+
+    /// A human readable name, e.g. for the rebinding prompt
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::LeftUp => "left paddle up",
+            Action::LeftDown => "left paddle down",
+            Action::RightUp => "right paddle up",
+            Action::RightDown => "right paddle down",
+            Action::Pause => "pause",
+            Action::Quit => "quit",
+            Action::Serve => "serve",
+            Action::Mute => "mute sound",
+            Action::QuickSave => "quick save",
+            Action::QuickLoad => "quick load",
+        }
+    }
+}
+
+/// Proportional paddle input, e.g. from a gamepad stick, from -1.0 (full speed up)
+/// to 1.0 (full speed down). Zero while nothing analog is held.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct AnalogInput {
+    pub left: f32,
+    pub right: f32,
+}
+
+/// Which key triggers each action.
+#[derive(Clone, PartialEq, Debug)]
+pub struct KeyBindings {
+    keys: HashMap<Action, Keycode>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let mut keys = HashMap::new();
+        keys.insert(Action::LeftUp, Keycode::W);
+        keys.insert(Action::LeftDown, Keycode::S);
+        keys.insert(Action::RightUp, Keycode::Up);
+        keys.insert(Action::RightDown, Keycode::Down);
+        keys.insert(Action::Pause, Keycode::P);
+        keys.insert(Action::Quit, Keycode::Escape);
+        keys.insert(Action::Serve, Keycode::Space);
+        keys.insert(Action::Mute, Keycode::M);
+        keys.insert(Action::QuickSave, Keycode::F5);
+        keys.insert(Action::QuickLoad, Keycode::F9);
+        KeyBindings { keys }
+    }
+}
+
+impl KeyBindings {
+    pub fn key(&self, action: Action) -> Option<Keycode> {
+        self.keys.get(&action).copied()
+    }
+
+    pub fn action(&self, key: Keycode) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|a| self.keys.get(a) == Option::Some(&key))
+    }
+
+    /// Binds `key` to `action`. An action that already used `key` takes over the
+    /// action's previous key, so no two actions ever share a key.
+    pub fn rebind(&mut self, action: Action, key: Keycode) {
+        let previous = self.keys.insert(action, key);
+        for a in Action::ALL.iter() {
+            if *a != action && self.keys.get(a) == Option::Some(&key) {
+                match previous {
+                    Option::Some(p) => self.keys.insert(*a, p),
+                    Option::None => self.keys.remove(a),
+                };
+            }
+        }
+    }
+}
+
+/// Walks through every action, binding each to the next key pressed.
+pub struct Rebinder {
+    next: usize,
+}
+
+impl Rebinder {
+    pub fn new() -> Rebinder {
+        Rebinder { next: 0 }
+    }
+
+    /// The action waiting for a key, or `None` once every action has been bound.
+    pub fn current(&self) -> Option<Action> {
+        Action::ALL.get(self.next).copied()
+    }
+
+    /// Binds the current action to `key` and moves on to the next one.
+    /// Returns whether rebinding is finished.
+    pub fn press(&mut self, bindings: &mut KeyBindings, key: Keycode) -> bool {
+        if let Option::Some(action) = self.current() {
+            bindings.rebind(action, key);
+            self.next += 1;
+        }
+        self.current().is_none()
+    }
+}
+
+impl Default for Rebinder {
+    fn default() -> Self {
+        Rebinder::new()
+    }
+}
@@ -0,0 +1,26 @@
+extern crate rand;
+extern crate sdl2;
+
+pub mod actor;
+pub mod actors;
+pub mod audio;
+pub mod collision_helper;
+pub mod config;
+pub mod controller;
+pub mod controllers;
+pub mod event;
+pub mod game;
+pub mod gamepad;
+pub mod headless;
+pub mod input;
+pub mod math_helper;
+pub mod net;
+pub mod renderer;
+pub mod renderers;
+pub mod replay;
+pub mod rollback;
+pub mod rules;
+pub mod sdl_audio;
+pub mod snapshot;
+pub mod tuning;
+pub mod world;
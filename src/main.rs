@@ -1,36 +1,219 @@
+extern crate pong;
 extern crate rand;
 extern crate sdl2;
 
-use actor::{UpdateInfo, Vec2};
-use actors::ball::Ball;
-use actors::player_paddle::PlayerPaddle;
-use actors::scoreboard::Scoreboard;
-use actors::wall::{Wall, WallType};
+use pong::config::{Config, DEFAULT_CONFIG_PATH};
+use pong::controllers::ai::Difficulty;
+use pong::game::{Game, GameSnapshot, GameState};
+use pong::gamepad::Gamepads;
+use pong::headless::HeadlessRunner;
+use pong::input::{Action, Rebinder};
+use pong::net::{resolve, Client, Host, Link, LinkConditions, NetState, PeerEvent, CLIENT_SIDE};
+use pong::renderer::{Color, Renderer};
+use pong::renderers::sdl::SdlRenderer;
+use pong::renderers::software::{ImageFormat, SoftwareRenderer};
+use pong::replay::Replay;
+use pong::rollback::{RollbackSession, TickInput};
+use pong::rules::MatchRules;
+use pong::sdl_audio::SdlAudio;
+use pong::snapshot::DEFAULT_SNAPSHOT_PATH;
+use pong::world::{
+    GameSettings, World, FONT_PATH, FONT_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH, TARGET_FRAMETIME_MICROS,
+};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels;
 
-use std::cell::RefCell;
 use std::collections::HashSet;
+use std::path::Path;
 use std::time::{Duration, Instant};
 
-mod actor;
-mod actors;
-mod collision_helper;
-mod math_helper;
+/// Starts rebinding every action, one key press at a time
+const REBIND_KEY: Keycode = Keycode::F1;
+/// How long `--join` and `--rollback-join` wait for the host to answer
+const JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long `--rollback-host` waits for the other player to join
+const HOST_TIMEOUT: Duration = Duration::from_secs(120);
+/// How long a rollback match waits for the other player before saying so on screen
+const STALL_NOTICE: Duration = Duration::from_millis(250);
+
+/// Who runs the match: this game alone, this game for a client, a host elsewhere, or
+/// this game and a peer together
+enum Network {
+    Local,
+    Host(Host),
+    Client(Client),
+    Rollback(RollbackSession),
+}
 
-const SCREEN_WIDTH: i16 = 800;
-const SCREEN_HEIGHT: i16 = 600;
+fn main() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().collect();
 
-const TARGET_FRAMERATE: f32 = 60.0;
-const TARGET_FRAMETIME_MICROS: u128 = (1.0 / TARGET_FRAMERATE * 1_000_000.0) as u128;
+    let seed = match arg_value(&args, "--seed")? {
+        Option::Some(s) => s.parse().map_err(|e| format!("Invalid --seed: {}", e))?,
+        Option::None => rand::random(),
+    };
 
-const FIXED_UPDATE_RATE: f32 = 60.0;
-const FIXED_UPDATE_TIME_SECS_F32: f32 = 1.0 / FIXED_UPDATE_RATE;
-const FIXED_UPDATE_TIME_MICROS_U64: u64 = (FIXED_UPDATE_TIME_SECS_F32 * 1_000_000.0) as u64;
-const FIXED_UPDATE_TIME_MICROS_U128: u128 = FIXED_UPDATE_TIME_MICROS_U64 as u128;
+    let mut frame_dumper = match arg_value(&args, "--dump-frames")? {
+        Option::Some(dir) => {
+            let format = match arg_value(&args, "--dump-format")? {
+                Option::None => ImageFormat::Png,
+                Option::Some(f) => ImageFormat::from_extension(f)
+                    .ok_or(format!("Unknown --dump-format {}, expected png or ppm", f))?,
+            };
+            let mut r = SoftwareRenderer::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+            r.load_font(FONT_PATH, FONT_SIZE as f32)?;
+            r.dump_frames_to(Path::new(dir), format)?;
+            Option::Some(r)
+        }
+        Option::None => Option::None,
+    };
+
+    let config_path = arg_value(&args, "--config")?
+        .map(|s| s.as_str())
+        .unwrap_or(DEFAULT_CONFIG_PATH);
+    let mut config = Config::load(config_path)?;
+    let snapshot_path = arg_value(&args, "--snapshot")?
+        .map(|s| s.as_str())
+        .unwrap_or(DEFAULT_SNAPSHOT_PATH);
+
+    let rules = match arg_value(&args, "--rules")? {
+        Option::Some(name) => MatchRules::preset(name).ok_or(format!(
+            "Unknown --rules {}, expected classic or table_tennis",
+            name
+        ))?,
+        Option::None => config.rules,
+    };
+    let mut multi_ball = config.multi_ball;
+    if let Option::Some(n) = arg_value(&args, "--multi-ball")? {
+        multi_ball.max_balls = n
+            .parse()
+            .map_err(|e| format!("Invalid number of balls for --multi-ball: {}", e))?;
+        multi_ball
+            .validate()
+            .map_err(|e| format!("Invalid --multi-ball: {}", e))?;
+    }
+    let mut settings = GameSettings {
+        left_ai: ai_arg(&args, "--ai-left")?,
+        right_ai: ai_arg(&args, "--ai-right")?,
+        rules,
+        ball: config.ball,
+        multi_ball,
+        remote: Option::None,
+    };
+
+    let replay_path = arg_value(&args, "--replay")?;
+    let replaying = replay_path.is_some();
+    let host_port = arg_value(&args, "--host")?;
+    let join_address = arg_value(&args, "--join")?;
+    let rollback_port = arg_value(&args, "--rollback-host")?;
+    let rollback_address = arg_value(&args, "--rollback-join")?;
+    let network_flags = [host_port, join_address, rollback_port, rollback_address]
+        .iter()
+        .filter(|f| f.is_some())
+        .count();
+    if network_flags > 0 && (replaying || arg_value(&args, "--headless")?.is_some()) {
+        return Result::Err("Network play can't be used with --replay or --headless".to_string());
+    }
+    if network_flags > 1 {
+        return Result::Err(
+            "Only one of --host, --join, --rollback-host and --rollback-join can be used"
+                .to_string(),
+        );
+    }
+    let conditions = LinkConditions {
+        latency: millis_arg(&args, "--net-latency")?,
+        jitter: millis_arg(&args, "--net-jitter")?,
+        loss: match arg_value(&args, "--net-loss")? {
+            Option::Some(p) => {
+                let percent: f32 = p
+                    .parse()
+                    .map_err(|e| format!("Invalid percentage for --net-loss: {}", e))?;
+                if !(0.0..=100.0).contains(&percent) {
+                    return Result::Err("--net-loss must be between 0 and 100".to_string());
+                }
+                percent / 100.0
+            }
+            Option::None => 0.0,
+        },
+    };
+
+    if host_port.is_some() {
+        settings.remote = Option::Some(CLIENT_SIDE);
+    }
+
+    let mut network = Network::Local;
+    if let Option::Some(address) = join_address {
+        println!("Joining {}", address);
+        let mut client = Client::connect(address)?;
+        client.set_conditions(conditions);
+        client
+            .wait_for_welcome(JOIN_TIMEOUT)
+            .map_err(|e| format!("Failed to join {}: {}", address, e))?;
+        network = Network::Client(client);
+    }
+    if let Option::Some(address) = rollback_address {
+        println!("Joining {}", address);
+        let mut link = Link::bind(0)?;
+        link.set_conditions(conditions);
+        let session = RollbackSession::join(link, resolve(address)?, JOIN_TIMEOUT)
+            .map_err(|e| format!("Failed to join {}: {}", address, e))?;
+        network = Network::Rollback(session);
+    }
+
+    let welcome = match &network {
+        Network::Client(client) => client.welcome().cloned(),
+        Network::Rollback(session) => Option::Some(session.welcome().clone()),
+        Network::Local | Network::Host(_) => Option::None,
+    };
+    let mut world = match (replay_path, welcome) {
+        (Option::Some(path), _) => World::from_replay(Replay::load(path)?),
+        // The other side picked the seed and settings
+        (Option::None, Option::Some(welcome)) => World::new(welcome.seed, welcome.settings),
+        (Option::None, Option::None) => World::new(seed, settings),
+    };
+
+    let record_path = arg_value(&args, "--record")?;
+    if record_path.is_some() {
+        world.start_recording()?;
+    }
+
+    if let Option::Some(seconds) = arg_value(&args, "--headless")? {
+        let seconds: u64 = seconds
+            .parse()
+            .map_err(|e| format!("Invalid number of seconds for --headless: {}", e))?;
+        let mut runner = HeadlessRunner::with_world(world);
+        run_headless(
+            &mut runner,
+            Duration::from_secs(seconds),
+            frame_dumper.as_mut(),
+        )?;
+        let recordings = runner.world_mut().take_recording().into_iter().collect();
+        return save_recordings(recordings, record_path);
+    }
+
+    println!("Seed: {}", world.seed());
+
+    if let Option::Some(port) = host_port {
+        let port = port
+            .parse()
+            .map_err(|e| format!("Invalid port for --host: {}", e))?;
+        let mut host = Host::bind(port, &world)?;
+        host.set_conditions(conditions);
+        println!("Hosting on port {}", host.port()?);
+        network = Network::Host(host);
+    }
+    if let Option::Some(port) = rollback_port {
+        let port = port
+            .parse()
+            .map_err(|e| format!("Invalid port for --rollback-host: {}", e))?;
+        let mut link = Link::bind(port)?;
+        link.set_conditions(conditions);
+        println!("Waiting for the other player on port {}", link.port()?);
+        let session = RollbackSession::host(link, &world, HOST_TIMEOUT)?;
+        println!("Player joined from {}", session.peer());
+        network = Network::Rollback(session);
+    }
 
-fn main() -> Result<(), String> {
     let sdl_context = sdl2::init()?;
     let video_subsys = sdl_context.video()?;
     let window = video_subsys
@@ -40,183 +223,416 @@ fn main() -> Result<(), String> {
         .build()
         .map_err(|e| e.to_string())?;
 
-    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
-
-    canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
-    canvas.clear();
-    canvas.present();
+    let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
 
     let ttf_context = sdl2::ttf::init().expect("f");
     let font = ttf_context
-        .load_font("fonts/roboto_mono/RobotoMono-VariableFont_wght.ttf", 40)
-        .expect(
-            "Failed to load font at fonts/roboto_mono/RobotoMono-VariableFont_wght.ttf",
-        );
+        .load_font(FONT_PATH, FONT_SIZE)
+        .unwrap_or_else(|_| panic!("Failed to load font at {}", FONT_PATH));
 
-    let mut events = sdl_context.event_pump()?;
+    let mut renderer = SdlRenderer::new(canvas, &font);
+    renderer.clear(Color::BLACK)?;
+    renderer.present()?;
 
-    let mut last_frame = Instant::now();
-    let mut time_since_fixed_update = Duration::from_micros(0);
+    let mut events = sdl_context.event_pump()?;
 
-    let mut update_info = UpdateInfo {
-        keys_pressed: HashSet::new(),
-        actors: Vec::new(),
-        elapsed: Duration::from_nanos(0),
-        elapsed_sec_f32: 0.0,
+    // Without the controller subsystem the game still runs, just without gamepads
+    let controller_subsystem = match sdl_context.game_controller() {
+        Result::Ok(s) => Option::Some(s),
+        Result::Err(e) => {
+            println!("Game controllers unavailable: {}", e);
+            Option::None
+        }
     };
+    // Likewise without sound. SDL_AUDIODRIVER=dummy runs the mixer without a sound card.
+    let mut audio = match sdl_context
+        .audio()
+        .and_then(|a| SdlAudio::new(&a, config.audio))
+    {
+        Result::Ok(a) => Option::Some(a),
+        Result::Err(e) => {
+            println!("Sound unavailable: {}", e);
+            Option::None
+        }
+    };
+    let mut open_controllers = Vec::new();
+    let mut gamepads = Gamepads::new();
+    let mut keyboard_actions = HashSet::new();
 
-    let half_width = SCREEN_WIDTH as f32 / 2.0;
-    let half_height = SCREEN_HEIGHT as f32 / 2.0;
-
-    let player = PlayerPaddle::new(
-        &Vec2 {
-            x: 40.0,
-            y: half_height,
-        },
-        true,
-    );
-    update_info.actors.push(RefCell::new(Box::new(player)));
-
-    let player = PlayerPaddle::new(
-        &Vec2 {
-            x: SCREEN_WIDTH as f32 - 40.0,
-            y: half_height,
-        },
-        false,
-    );
-    update_info.actors.push(RefCell::new(Box::new(player)));
-
-    let ball = Ball::new(&Vec2 {
-        x: half_width,
-        y: half_height,
-    });
-    update_info.actors.push(RefCell::new(Box::new(ball)));
-
-    let wall = Wall::new(
-        &Vec2 {
-            x: half_width,
-            y: -half_height,
-        },
-        SCREEN_WIDTH as f32,
-        SCREEN_HEIGHT as f32,
-        WallType::Regular,
-    );
-    update_info.actors.push(RefCell::new(Box::new(wall)));
-
-    let wall = Wall::new(
-        &Vec2 {
-            x: half_width,
-            y: 3.0 * half_height,
-        },
-        SCREEN_WIDTH as f32,
-        SCREEN_HEIGHT as f32,
-        WallType::Regular,
-    );
-    update_info.actors.push(RefCell::new(Box::new(wall)));
-
-    let wall = Wall::new(
-        &Vec2 {
-            x: -half_width,
-            y: half_height,
-        },
-        SCREEN_WIDTH as f32,
-        SCREEN_HEIGHT as f32,
-        WallType::Left,
-    );
-    update_info.actors.push(RefCell::new(Box::new(wall)));
+    let mut rebinder: Option<Rebinder> = Option::None;
 
-    let wall = Wall::new(
-        &Vec2 {
-            x: 3.0 * half_width,
-            y: half_height,
-        },
-        SCREEN_WIDTH as f32,
-        SCREEN_HEIGHT as f32,
-        WallType::Right,
-    );
-    update_info.actors.push(RefCell::new(Box::new(wall)));
+    let mut game = Game::new(world);
+    set_serve_key_name(&mut game, &config);
+    if replaying {
+        game.start();
+    }
 
-    let scoreboard = Scoreboard::new(
-        &Vec2 {
-            x: half_width,
-            y: 50.0,
-        },
-        &font,
-    );
-    update_info.actors.push(RefCell::new(Box::new(scoreboard)));
+    let mut last_frame = Instant::now();
+    let mut reported_replay_end = false;
+    // Since when a rollback match has been waiting for the other player
+    let mut stalled_since: Option<Instant> = Option::None;
 
     'main: loop {
         let now = Instant::now();
-        update_info.elapsed = now.duration_since(last_frame);
-        if update_info.elapsed.as_micros() >= TARGET_FRAMETIME_MICROS {
+        let elapsed = now.duration_since(last_frame);
+        if elapsed.as_micros() >= TARGET_FRAMETIME_MICROS {
             last_frame = now;
 
-            update_info.elapsed_sec_f32 = update_info.elapsed.as_micros() as f32 / 1_000_000.0;
-
-            canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
-            canvas.clear();
-
             for event in events.poll_iter() {
                 match event {
                     Event::Quit { .. } => break 'main,
 
                     Event::KeyDown {
                         keycode: Some(keycode),
+                        repeat,
                         ..
                     } => {
-                        if keycode == Keycode::Escape {
-                            break 'main;
+                        if let Option::Some(r) = rebinder.as_mut() {
+                            if !repeat && r.press(&mut config.key_bindings, keycode) {
+                                rebinder = Option::None;
+                                set_serve_key_name(&mut game, &config);
+                                config.save(config_path)?;
+                                println!("Saved key bindings to {}", config_path);
+                            }
+                            continue;
+                        }
+                        if keycode == REBIND_KEY {
+                            rebinder = Option::Some(Rebinder::new());
+                            keyboard_actions.clear();
+                            continue;
+                        }
+
+                        match config.key_bindings.action(keycode) {
+                            Option::Some(action) => {
+                                if !repeat && !press(&mut game, &mut network, action) {
+                                    break 'main;
+                                }
+                                match action {
+                                    Action::Mute => {
+                                        if let Option::Some(a) = audio.as_mut() {
+                                            if !repeat {
+                                                a.toggle_mute();
+                                            }
+                                        }
+                                    }
+                                    Action::QuickSave => {
+                                        if !repeat {
+                                            quick_save(&game, &network, snapshot_path);
+                                        }
+                                    }
+                                    Action::QuickLoad => {
+                                        if !repeat {
+                                            quick_load(
+                                                &mut game,
+                                                &network,
+                                                replaying,
+                                                snapshot_path,
+                                            );
+                                        }
+                                    }
+                                    Action::Pause => {}
+                                    _ => {
+                                        keyboard_actions.insert(action);
+                                    }
+                                }
+                            }
+                            Option::None => {
+                                // Steps a single fixed update of a paused replay
+                                if replaying
+                                    && game.state() == GameState::Paused
+                                    && keycode == Keycode::Period
+                                {
+                                    game.world_mut().fixed_update();
+                                }
+                            }
                         }
-                        update_info.keys_pressed.insert(keycode);
                     }
 
                     Event::KeyUp {
                         keycode: Some(keycode),
                         ..
                     } => {
-                        if keycode == Keycode::Escape {
-                            break 'main;
+                        if let Option::Some(action) = config.key_bindings.action(keycode) {
+                            keyboard_actions.remove(&action);
                         }
-                        update_info.keys_pressed.remove(&keycode);
                     }
 
-                    _ => {}
+                    Event::ControllerDeviceAdded { which, .. } => {
+                        if let Option::Some(subsystem) = controller_subsystem.as_ref() {
+                            match subsystem.open(which) {
+                                Result::Ok(c) => {
+                                    if gamepads.connect(c.instance_id()) {
+                                        println!("Connected controller {}", c.name());
+                                    }
+                                    open_controllers.push(c);
+                                }
+                                Result::Err(e) => println!("Failed to open controller: {}", e),
+                            }
+                        }
+                    }
+
+                    Event::ControllerDeviceRemoved { which, .. } => {
+                        gamepads.handle_event(&event);
+                        open_controllers.retain(|c| c.instance_id() != which);
+                    }
+
+                    _ => {
+                        if let Option::Some(action) = gamepads.handle_event(&event) {
+                            if !press(&mut game, &mut network, action) {
+                                break 'main;
+                            }
+                        }
+                    }
                 }
             }
 
-            let mut i = 0;
-            while i < update_info.actors.len() {
-                if let Option::Some(a) = update_info.actors.get(i) {
-                    a.borrow_mut().update(&update_info);
+            let mut actions = keyboard_actions.clone();
+            gamepads.add_actions(&mut actions);
+            let analog = gamepads.analog();
+            if !replaying {
+                let world = game.world_mut();
+                match &mut network {
+                    Network::Local => {
+                        world.update_info.actions_pressed = actions.clone();
+                        world.update_info.analog = analog;
+                    }
+                    // Both players may use either set of keys for their own paddle
+                    Network::Host(host) => host.set_input(world, &actions, analog),
+                    Network::Client(client) => client.send_input(&actions, analog)?,
+                    // The session sets the input of every fixed update itself
+                    Network::Rollback(_) => {}
                 }
-                i += 1;
             }
 
-            time_since_fixed_update += update_info.elapsed;
-            update_info.elapsed = Duration::from_micros(FIXED_UPDATE_TIME_MICROS_U64);
-            update_info.elapsed_sec_f32 = FIXED_UPDATE_TIME_SECS_F32;
-            while time_since_fixed_update.as_micros() > FIXED_UPDATE_TIME_MICROS_U128 {
-                i = 0;
-                while i < update_info.actors.len() {
-                    if let Option::Some(a) = update_info.actors.get(i) {
-                        a.borrow_mut().fixed_update(&update_info);
+            // Keep talking to the other side while rebinding, so it doesn't time out
+            match &mut network {
+                Network::Local => {}
+                Network::Host(host) => {
+                    for action in host.poll()? {
+                        if rebinder.is_none() {
+                            game.press(action);
+                        }
+                    }
+                    for event in host.take_peer_events() {
+                        match event {
+                            PeerEvent::Joined(from) => println!("Player joined from {}", from),
+                            PeerEvent::Left(from) => println!("Player at {} left", from),
+                            PeerEvent::TimedOut(from) => println!("Player at {} timed out", from),
+                        }
+                    }
+                }
+                Network::Client(client) => {
+                    if let Option::Some(state) = client.poll()? {
+                        state.apply(&mut game);
+                        if let Option::Some(a) = audio.as_mut() {
+                            a.play_events(&state.events, &game.world().settings().ball);
+                        }
+                    }
+                    if client.host_left() {
+                        println!("The host left");
+                        break 'main;
+                    }
+                }
+                Network::Rollback(session) => {
+                    if rebinder.is_none() {
+                        let input = TickInput::for_side(&actions, analog, session.side());
+                        if session.advance(&mut game, input)? {
+                            stalled_since = Option::None;
+                        } else if stalled_since.is_none() {
+                            stalled_since = Option::Some(now);
+                        }
+                    } else {
+                        session.poll(&mut game)?;
+                        stalled_since = Option::None;
+                    }
+                    if let Option::Some(a) = audio.as_mut() {
+                        a.play_events(&session.take_events(), &game.world().settings().ball);
                     }
-                    i += 1;
                 }
-                time_since_fixed_update -= update_info.elapsed;
             }
 
-            i = 0;
-            while i < update_info.actors.len() {
-                if let Option::Some(a) = update_info.actors.get(i) {
-                    let _ = a.borrow().draw(&mut canvas);
+            if rebinder.is_none() {
+                // A client only shows what the host sends, and a rollback session steps
+                // the game itself
+                if matches!(network, Network::Local | Network::Host(_)) {
+                    game.step(elapsed);
+                    if let Option::Some(a) = audio.as_mut() {
+                        a.play_events(game.world().events(), &game.world().settings().ball);
+                    }
                 }
-                i += 1;
+                if let Network::Host(host) = &mut network {
+                    host.send_state(NetState::capture(&game))?;
+                }
+            }
+
+            if game.world().replay_finished() && !reported_replay_end {
+                println!(
+                    "Replay finished after {} fixed updates",
+                    game.world().fixed_updates()
+                );
+                reported_replay_end = true;
+            }
+
+            game.draw(&mut renderer)?;
+            if let Option::Some(action) = rebinder.as_ref().and_then(|r| r.current()) {
+                renderer.draw_text(
+                    &format!("Press key: {}", action.description()),
+                    SCREEN_WIDTH as i32 / 2,
+                    SCREEN_HEIGHT as i32 / 2,
+                    Color::WHITE,
+                )?;
+            }
+            if stalled_since.is_some_and(|t| t.elapsed() >= STALL_NOTICE) {
+                renderer.draw_text(
+                    "Waiting for the other player",
+                    SCREEN_WIDTH as i32 / 2,
+                    SCREEN_HEIGHT as i32 / 2,
+                    Color::WHITE,
+                )?;
+            }
+            renderer.present()?;
+
+            if let Option::Some(r) = frame_dumper.as_mut() {
+                game.draw(r)?;
+                r.present()?;
             }
+        }
+    }
+
+    save_recordings(game.take_recordings(), record_path)
+}
 
-            canvas.present();
+/// Saves the first match to `path` and any later ones to `path.2`, `path.3` and so on.
+fn save_recordings(recordings: Vec<Replay>, path: Option<&String>) -> Result<(), String> {
+    let path = match path {
+        Option::Some(p) => p,
+        Option::None => return Result::Ok(()),
+    };
+    for (i, replay) in recordings.iter().enumerate() {
+        let path = match i {
+            0 => path.clone(),
+            _ => format!("{}.{}", path, i + 1),
+        };
+        replay.save(&path)?;
+        println!("Saved replay of {} fixed updates to {}", replay.ticks, path);
+    }
+    Result::Ok(())
+}
+
+/// Passes a single press to the game, or on to whoever else runs it.
+/// Returns whether the game should keep running.
+fn press(game: &mut Game, network: &mut Network, action: Action) -> bool {
+    match network {
+        Network::Client(client) => {
+            client.press(action);
+            action != Action::Quit
+        }
+        Network::Rollback(session) => {
+            session.press(action);
+            action != Action::Quit
         }
+        Network::Local | Network::Host(_) => game.press(action),
     }
+}
 
+/// Saves the whole match to `path`. Only a game that runs the match itself has one to save.
+fn quick_save(game: &Game, network: &Network, path: &str) {
+    if !matches!(network, Network::Local | Network::Host(_)) {
+        println!("Only a local or hosted match can be saved");
+        return;
+    }
+    match game.snapshot().save(path) {
+        Result::Ok(()) => println!("Saved snapshot to {}", path),
+        Result::Err(e) => println!("{}", e),
+    }
+}
+
+/// Goes back to the match saved at `path`. The other player of a rollback session would
+/// go on without it and a replay can't change what happened, so neither can load.
+fn quick_load(game: &mut Game, network: &Network, replaying: bool, path: &str) {
+    if replaying {
+        println!("Snapshots can't be loaded while replaying");
+        return;
+    }
+    if !matches!(network, Network::Local | Network::Host(_)) {
+        println!("Snapshots can only be loaded into a local or hosted match");
+        return;
+    }
+    match GameSnapshot::load(path).and_then(|s| game.restore(&s)) {
+        Result::Ok(()) => println!("Loaded snapshot from {}", path),
+        Result::Err(e) => println!("{}", e),
+    }
+}
+
+fn set_serve_key_name(game: &mut Game, config: &Config) {
+    if let Option::Some(key) = config.key_bindings.key(Action::Serve) {
+        game.set_serve_key_name(&key.name());
+    }
+}
+
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Result<Option<&'a String>, String> {
+    match args.iter().position(|a| a == flag) {
+        Option::Some(i) => args
+            .get(i + 1)
+            .map(Option::Some)
+            .ok_or(format!("{} requires a value", flag)),
+        Option::None => Result::Ok(Option::None),
+    }
+}
+
+fn millis_arg(args: &[String], flag: &str) -> Result<Duration, String> {
+    match arg_value(args, flag)? {
+        Option::Some(ms) => ms
+            .parse()
+            .map(Duration::from_millis)
+            .map_err(|e| format!("Invalid milliseconds for {}: {}", flag, e)),
+        Option::None => Result::Ok(Duration::ZERO),
+    }
+}
+
+fn ai_arg(args: &[String], flag: &str) -> Result<Option<Difficulty>, String> {
+    match arg_value(args, flag)? {
+        Option::Some(name) => Difficulty::from_name(name).map(Option::Some).ok_or(format!(
+            "Unknown difficulty {} for {}, expected easy, medium or hard",
+            name, flag
+        )),
+        Option::None => Result::Ok(Option::None),
+    }
+}
+
+fn run_headless(
+    runner: &mut HeadlessRunner,
+    duration: Duration,
+    mut frame_dumper: Option<&mut SoftwareRenderer>,
+) -> Result<(), String> {
+    while runner.simulated_time() < duration
+        && !runner.world().replay_finished()
+        && runner.world().match_winner().is_none()
+    {
+        runner.step();
+        if let Option::Some(r) = frame_dumper.as_deref_mut() {
+            runner.draw(r)?;
+            r.present()?;
+        }
+    }
+
+    let scores = runner
+        .scores()
+        .ok_or("Headless world did not contain a scoreboard")?;
+    println!(
+        "Simulated {:.1}s with seed {}: {} : {}",
+        runner.simulated_time().as_secs_f32(),
+        runner.world().seed(),
+        scores.left_score,
+        scores.right_score
+    );
+    if let Option::Some(winner) = runner.world().match_winner() {
+        println!(
+            "{:?} won the match {} games to {}",
+            winner,
+            scores.left_games.max(scores.right_games),
+            scores.left_games.min(scores.right_games)
+        );
+    }
     Ok(())
 }
@@ -1,12 +1,16 @@
 use std::f32::consts;
 
-use rand;
+use rand::Rng;
+
+/// The generator behind all randomness in the simulation. ChaCha produces the same
+/// sequence for a seed on every platform, which `StdRng` does not promise.
+pub type GameRng = rand_chacha::ChaCha8Rng;
 
 pub fn get_point_on_unit_circle(theta: f32) -> (f32, f32) {
     (theta.cos(), theta.sin())
 }
 
-pub fn get_random_point_on_unit_circle() -> (f32, f32) {
-    let theta: f32 = rand::random::<f32>() * 2.0 * consts::PI;
+pub fn get_random_point_on_unit_circle(rng: &mut GameRng) -> (f32, f32) {
+    let theta: f32 = rng.gen::<f32>() * 2.0 * consts::PI;
     get_point_on_unit_circle(theta)
 }
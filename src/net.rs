@@ -0,0 +1,943 @@
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::actor::{Actor, ActorData, Tag, Vec2};
+use crate::actors::ball::Ball;
+use crate::actors::player_paddle::PlayerPaddle;
+use crate::actors::scoreboard::ScoreboardData;
+use crate::controllers::local::{intent_for, Side};
+use crate::event::GameEvent;
+use crate::game::{Game, GameState};
+use crate::input::{Action, AnalogInput};
+use crate::replay::{parse_axis, parse_next, Replay};
+use crate::rollback::{InputWindow, TickInput};
+use crate::snapshot::{
+    game_state_text, parse_game_state, parse_scores, parse_side, parse_vec2, scores_text, side_name,
+};
+use crate::world::World;
+
+pub const DEFAULT_PORT: u16 = 7777;
+/// The host plays the left paddle, whoever joins the right one
+pub const HOST_SIDE: Side = Side::Left;
+pub const CLIENT_SIDE: Side = Side::Right;
+
+const MAX_PACKET_SIZE: usize = 8192;
+/// How often a client asks to join until the host answers
+const JOIN_INTERVAL: Duration = Duration::from_millis(500);
+/// Either side gives up on the other after hearing nothing for this long
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Everything sent between a host and a client. Sent as text, one `key values` entry
+/// per line like a replay, with the kind of message on the first line.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Message {
+    /// A client asking to play
+    Join,
+    /// The host accepting, with the settings of the match
+    Welcome(Replay),
+    Input(RemoteInput),
+    State(NetState),
+    /// A peer's input in a rollback match
+    Inputs(InputWindow),
+    Leave,
+}
+
+/// What the player at a client is doing.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct RemoteInput {
+    /// Actions held down right now
+    pub held: Vec<Action>,
+    pub analog: AnalogInput,
+    /// Times serve and pause were pressed since joining. Counting presses instead of
+    /// sending each one once means a lost packet can't lose a press.
+    pub serves: u32,
+    pub pauses: u32,
+}
+
+/// What a client needs to show the match, sent by the host every frame.
+#[derive(Clone, PartialEq, Debug)]
+pub struct NetState {
+    /// Counts up with every state sent, so a client can drop ones arriving out of order
+    pub frame: u64,
+    pub state: GameState,
+    pub scores: ScoreboardData,
+    pub paddles: Vec<Vec2>,
+    pub balls: Vec<Vec2>,
+    /// Everything that happened since the last state, e.g. for sound
+    pub events: Vec<GameEvent>,
+}
+
+impl NetState {
+    pub fn capture(game: &Game) -> NetState {
+        let info = &game.world().update_info;
+        NetState {
+            frame: 0,
+            state: game.state(),
+            scores: game.world().scores().unwrap_or_default(),
+            paddles: info.all::<PlayerPaddle>().map(|p| *p.position()).collect(),
+            balls: info.all::<Ball>().map(|b| *b.position()).collect(),
+            events: game.world().events().to_vec(),
+        }
+    }
+
+    /// Makes `game` look like the host's game. Only moves actors around, nothing is simulated.
+    pub fn apply(&self, game: &mut Game) {
+        game.set_state(self.state);
+        let world = game.world_mut();
+        match_ball_count(world, self.balls.len());
+
+        let info = &world.update_info;
+        let mut paddles = self.paddles.iter();
+        let mut balls = self.balls.iter();
+        for a in info.actors.iter() {
+            let mut actor = a.borrow_mut();
+            let target = if actor.has_tag(Tag::Paddle) {
+                paddles.next()
+            } else if actor.has_tag(Tag::Ball) {
+                balls.next()
+            } else {
+                Option::None
+            };
+            if let Option::Some(position) = target {
+                actor.set_position(position);
+            }
+            if actor.has_tag(Tag::Scoreboard) {
+                actor.set_data(ActorData::Scoreboard(self.scores));
+            }
+        }
+    }
+}
+
+/// Adds or removes balls until the world has `count` of them.
+fn match_ball_count(world: &mut World, count: usize) {
+    let settings = *world.settings();
+    let info = &mut world.update_info;
+    let ids: Vec<_> = info.tagged(Tag::Ball).map(|(id, _)| id).collect();
+    for id in ids.iter().skip(count) {
+        info.despawn(*id);
+    }
+    info.apply_commands();
+    let mut i = ids.len();
+    while i < count {
+        let ball = Ball::new(
+            &Vec2 { x: 0.0, y: 0.0 },
+            &settings,
+            &mut info.rng.borrow_mut(),
+        );
+        info.add(Box::new(ball));
+        i += 1;
+    }
+}
+
+/// Moves the paddle controls in `actions` and `analog` onto `side`, so a player can
+/// steer their own paddle with either set of keys or either stick.
+pub fn input_for_side(
+    actions: &HashSet<Action>,
+    analog: AnalogInput,
+    side: Side,
+) -> (HashSet<Action>, AnalogInput) {
+    let (up, down) = match side {
+        Side::Left => (Action::LeftUp, Action::LeftDown),
+        Side::Right => (Action::RightUp, Action::RightDown),
+    };
+    let mut moved = HashSet::new();
+    for a in actions.iter() {
+        moved.insert(match a {
+            Action::LeftUp | Action::RightUp => up,
+            Action::LeftDown | Action::RightDown => down,
+            other => *other,
+        });
+    }
+    let axis = if analog.left != 0.0 {
+        analog.left
+    } else {
+        analog.right
+    };
+    let analog = match side {
+        Side::Left => AnalogInput {
+            left: axis,
+            right: 0.0,
+        },
+        Side::Right => AnalogInput {
+            left: 0.0,
+            right: axis,
+        },
+    };
+    (moved, analog)
+}
+
+/// Artificial network trouble, for trying network play out over loopback.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct LinkConditions {
+    /// Added to every packet sent
+    pub latency: Duration,
+    /// Up to this much more is added, different for every packet, so packets can arrive out of order
+    pub jitter: Duration,
+    /// Chance from 0.0 to 1.0 of a packet being dropped
+    pub loss: f32,
+}
+
+/// A nonblocking UDP socket that delays and drops the packets it sends as its
+/// `LinkConditions` ask. Without any conditions packets go out straight away.
+pub struct Link {
+    socket: UdpSocket,
+    conditions: LinkConditions,
+    /// Packets held back by the latency, with when to send them
+    delayed: Vec<(Instant, SocketAddr, Vec<u8>)>,
+}
+
+impl Link {
+    pub fn bind(port: u16) -> Result<Link, String> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))
+            .map_err(|e| format!("Failed to listen on port {}: {}", port, e))?;
+        socket.set_nonblocking(true).map_err(|e| e.to_string())?;
+        Result::Ok(Link {
+            socket,
+            conditions: LinkConditions::default(),
+            delayed: Vec::new(),
+        })
+    }
+
+    pub fn set_conditions(&mut self, conditions: LinkConditions) {
+        self.conditions = conditions;
+    }
+
+    pub fn port(&self) -> Result<u16, String> {
+        self.socket
+            .local_addr()
+            .map(|a| a.port())
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn send_to(&mut self, message: &Message, address: SocketAddr) -> Result<(), String> {
+        let c = self.conditions;
+        if c.loss > 0.0 && rand::random::<f32>() < c.loss {
+            return Result::Ok(());
+        }
+        let packet = message.to_text().into_bytes();
+        if c.latency.is_zero() && c.jitter.is_zero() {
+            return self.send_now(&packet, address);
+        }
+        let delay = c.latency + c.jitter.mul_f32(rand::random::<f32>());
+        self.delayed.push((Instant::now() + delay, address, packet));
+        self.flush()
+    }
+
+    /// Sends the delayed packets that are due.
+    pub fn flush(&mut self) -> Result<(), String> {
+        let now = Instant::now();
+        let mut i = 0;
+        while i < self.delayed.len() {
+            if self.delayed[i].0 <= now {
+                let (_, address, packet) = self.delayed.remove(i);
+                self.send_now(&packet, address)?;
+            } else {
+                i += 1;
+            }
+        }
+        Result::Ok(())
+    }
+
+    /// Sends every delayed packet now, e.g. before closing.
+    pub fn flush_all(&mut self) -> Result<(), String> {
+        for (_, address, packet) in std::mem::take(&mut self.delayed) {
+            self.send_now(&packet, address)?;
+        }
+        Result::Ok(())
+    }
+
+    /// The next message waiting, skipping anything that isn't one.
+    pub fn receive(&mut self) -> Result<Option<(Message, SocketAddr)>, String> {
+        self.flush()?;
+        let mut buf = [0; MAX_PACKET_SIZE];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf) {
+                Result::Ok(r) => r,
+                Result::Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    return Result::Ok(Option::None)
+                }
+                // An earlier packet to someone that has gone away or isn't there yet
+                Result::Err(e)
+                    if e.kind() == ErrorKind::ConnectionReset
+                        || e.kind() == ErrorKind::ConnectionRefused =>
+                {
+                    continue
+                }
+                Result::Err(e) => return Result::Err(format!("Failed to receive: {}", e)),
+            };
+            if let Option::Some(message) = parse_packet(&buf[..len]) {
+                return Result::Ok(Option::Some((message, from)));
+            }
+        }
+    }
+
+    fn send_now(&self, packet: &[u8], address: SocketAddr) -> Result<(), String> {
+        match self.socket.send_to(packet, address) {
+            Result::Ok(_) => Result::Ok(()),
+            // UDP makes no promises anyway, a full buffer is just a lost packet
+            Result::Err(e)
+                if e.kind() == ErrorKind::WouldBlock
+                    || e.kind() == ErrorKind::ConnectionRefused =>
+            {
+                Result::Ok(())
+            }
+            Result::Err(e) => Result::Err(format!("Failed to send to {}: {}", address, e)),
+        }
+    }
+}
+
+/// Resolves `address`, adding the default port if it has none.
+pub fn resolve(address: &str) -> Result<SocketAddr, String> {
+    let address = if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, DEFAULT_PORT)
+    };
+    address
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to find {}: {}", address, e))?
+        .find(|a| a.is_ipv4())
+        .ok_or(format!("Failed to find {}", address))
+}
+
+/// A change in who is playing with a `Host`, for the game to report.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PeerEvent {
+    Joined(SocketAddr),
+    Left(SocketAddr),
+    TimedOut(SocketAddr),
+}
+
+/// Runs the match for a client that joins over UDP. The host simulates everything and
+/// only takes the client's input.
+pub struct Host {
+    link: Link,
+    welcome: Replay,
+    client: Option<Peer>,
+    frames_sent: u64,
+    peer_events: Vec<PeerEvent>,
+}
+
+struct Peer {
+    address: SocketAddr,
+    input: RemoteInput,
+    last_heard: Instant,
+    serves_seen: u32,
+    pauses_seen: u32,
+}
+
+impl Host {
+    /// Listens on `port` on every interface, for a match played with `world`'s settings,
+    /// which have to give the client's paddle to a remote player.
+    pub fn bind(port: u16, world: &World) -> Result<Host, String> {
+        if world.settings().remote != Option::Some(CLIENT_SIDE) {
+            return Result::Err("The client's paddle isn't set up for a remote player".to_string());
+        }
+        Result::Ok(Host {
+            link: Link::bind(port)?,
+            welcome: Replay::new(world.seed(), *world.settings()),
+            client: Option::None,
+            frames_sent: 0,
+            peer_events: Vec::new(),
+        })
+    }
+
+    pub fn set_conditions(&mut self, conditions: LinkConditions) {
+        self.link.set_conditions(conditions);
+    }
+
+    pub fn port(&self) -> Result<u16, String> {
+        self.link.port()
+    }
+
+    pub fn connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Takes every join and leave since the last call, oldest first.
+    pub fn take_peer_events(&mut self) -> Vec<PeerEvent> {
+        std::mem::take(&mut self.peer_events)
+    }
+
+    /// Reads every waiting packet and returns the serves and pauses the client pressed
+    /// since the last poll.
+    pub fn poll(&mut self) -> Result<Vec<Action>, String> {
+        while let Option::Some((message, from)) = self.link.receive()? {
+            self.handle(message, from)?;
+        }
+
+        if let Option::Some(c) = &self.client {
+            if c.last_heard.elapsed() > TIMEOUT {
+                self.peer_events.push(PeerEvent::TimedOut(c.address));
+                self.client = Option::None;
+            }
+        }
+
+        let mut pressed = Vec::new();
+        if let Option::Some(c) = self.client.as_mut() {
+            while c.serves_seen < c.input.serves {
+                pressed.push(Action::Serve);
+                c.serves_seen += 1;
+            }
+            while c.pauses_seen < c.input.pauses {
+                pressed.push(Action::Pause);
+                c.pauses_seen += 1;
+            }
+        }
+        Result::Ok(pressed)
+    }
+
+    fn handle(&mut self, message: Message, from: SocketAddr) -> Result<(), String> {
+        let from_client = self.client.as_ref().map(|c| c.address) == Option::Some(from);
+        match message {
+            Message::Join => {
+                if self.client.is_none() {
+                    self.peer_events.push(PeerEvent::Joined(from));
+                    self.client = Option::Some(Peer {
+                        address: from,
+                        input: RemoteInput::default(),
+                        last_heard: Instant::now(),
+                        serves_seen: 0,
+                        pauses_seen: 0,
+                    });
+                }
+                // Answer repeated joins too, in case the first welcome was lost
+                if self.client.as_ref().map(|c| c.address) == Option::Some(from) {
+                    let welcome = Message::Welcome(self.welcome.clone());
+                    self.link.send_to(&welcome, from)?;
+                }
+            }
+            Message::Input(input) if from_client => {
+                if let Option::Some(c) = self.client.as_mut() {
+                    c.input = input;
+                    c.last_heard = Instant::now();
+                }
+            }
+            Message::Leave if from_client => {
+                self.peer_events.push(PeerEvent::Left(from));
+                self.client = Option::None;
+            }
+            _ => {}
+        }
+        Result::Ok(())
+    }
+
+    /// Sets the input of the next fixed updates of `world`: the host player's `actions` and
+    /// `analog` on the host's paddle, and whatever the client is holding for the
+    /// `NetworkController` of theirs.
+    pub fn set_input(&self, world: &mut World, actions: &HashSet<Action>, analog: AnalogInput) {
+        let (actions, analog) = input_for_side(actions, analog, HOST_SIDE);
+        world.update_info.actions_pressed = actions;
+        world.update_info.analog = analog;
+        world.set_remote_intent(self.remote_intent());
+    }
+
+    /// How fast the client wants their paddle to move.
+    fn remote_intent(&self) -> f32 {
+        match &self.client {
+            Option::Some(c) => {
+                let held = c.input.held.iter().copied().collect();
+                let (held, analog) = input_for_side(&held, c.input.analog, CLIENT_SIDE);
+                intent_for(CLIENT_SIDE, &held, analog)
+            }
+            Option::None => 0.0,
+        }
+    }
+
+    pub fn send_state(&mut self, mut state: NetState) -> Result<(), String> {
+        let address = match &self.client {
+            Option::Some(c) => c.address,
+            Option::None => return Result::Ok(()),
+        };
+        state.frame = self.frames_sent;
+        self.frames_sent += 1;
+        self.link.send_to(&Message::State(state), address)
+    }
+}
+
+impl Drop for Host {
+    fn drop(&mut self) {
+        if let Option::Some(c) = &self.client {
+            let _ = self.link.send_to(&Message::Leave, c.address);
+        }
+        let _ = self.link.flush_all();
+    }
+}
+
+/// Plays the right paddle of a match run by a `Host`.
+pub struct Client {
+    link: Link,
+    host: SocketAddr,
+    welcome: Option<Replay>,
+    last_join: Option<Instant>,
+    last_heard: Instant,
+    last_frame: Option<u64>,
+    serves: u32,
+    pauses: u32,
+    host_left: bool,
+}
+
+impl Client {
+    /// Starts asking the host at `address`, e.g. `192.168.1.20:7777`, to join.
+    pub fn connect(address: &str) -> Result<Client, String> {
+        Result::Ok(Client {
+            link: Link::bind(0)?,
+            host: resolve(address)?,
+            welcome: Option::None,
+            last_join: Option::None,
+            last_heard: Instant::now(),
+            last_frame: Option::None,
+            serves: 0,
+            pauses: 0,
+            host_left: false,
+        })
+    }
+
+    pub fn set_conditions(&mut self, conditions: LinkConditions) {
+        self.link.set_conditions(conditions);
+    }
+
+    /// The seed and settings of the match, once the host has let this client in.
+    pub fn welcome(&self) -> Option<&Replay> {
+        self.welcome.as_ref()
+    }
+
+    /// Blocks until the host lets this client in, or fails after `timeout`.
+    pub fn wait_for_welcome(&mut self, timeout: Duration) -> Result<Replay, String> {
+        let started = Instant::now();
+        loop {
+            self.poll()?;
+            if let Option::Some(welcome) = self.welcome.as_ref() {
+                return Result::Ok(welcome.clone());
+            }
+            if started.elapsed() > timeout {
+                return Result::Err("The host did not answer".to_string());
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Whether the host has ended the match, after which polling returns nothing.
+    pub fn host_left(&self) -> bool {
+        self.host_left
+    }
+
+    /// Counts a press of serve or pause, to be sent with the next input.
+    pub fn press(&mut self, action: Action) {
+        match action {
+            Action::Serve => self.serves += 1,
+            Action::Pause => self.pauses += 1,
+            _ => {}
+        }
+    }
+
+    /// Reads every waiting packet and returns the newest state, if one arrived. Keeps
+    /// asking to join until the host answers.
+    pub fn poll(&mut self) -> Result<Option<NetState>, String> {
+        if self.host_left {
+            return Result::Ok(Option::None);
+        }
+        if self.welcome.is_none()
+            && self.last_join.map(|t| t.elapsed() >= JOIN_INTERVAL) != Option::Some(false)
+        {
+            self.link.send_to(&Message::Join, self.host)?;
+            self.last_join = Option::Some(Instant::now());
+        }
+
+        let mut newest: Option<NetState> = Option::None;
+        while let Option::Some((message, from)) = self.link.receive()? {
+            if from != self.host {
+                continue;
+            }
+            match message {
+                Message::Welcome(replay) => {
+                    self.last_heard = Instant::now();
+                    self.welcome = Option::Some(replay);
+                }
+                Message::State(state) => {
+                    self.last_heard = Instant::now();
+                    if self.last_frame.is_some_and(|f| state.frame <= f) {
+                        continue;
+                    }
+                    self.last_frame = Option::Some(state.frame);
+                    // Keep the events of states that are skipped over
+                    if let Option::Some(older) = newest.take() {
+                        let mut state = state;
+                        let mut events = older.events;
+                        events.append(&mut state.events);
+                        state.events = events;
+                        newest = Option::Some(state);
+                    } else {
+                        newest = Option::Some(state);
+                    }
+                }
+                Message::Leave => {
+                    self.host_left = true;
+                    return Result::Ok(newest);
+                }
+                _ => {}
+            }
+        }
+
+        if self.welcome.is_some() && self.last_heard.elapsed() > TIMEOUT {
+            return Result::Err("Lost connection to the host".to_string());
+        }
+        Result::Ok(newest)
+    }
+
+    pub fn send_input(
+        &mut self,
+        held: &HashSet<Action>,
+        analog: AnalogInput,
+    ) -> Result<(), String> {
+        let input = RemoteInput {
+            held: held.iter().copied().collect(),
+            analog,
+            serves: self.serves,
+            pauses: self.pauses,
+        };
+        self.link.send_to(&Message::Input(input), self.host)
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.link.send_to(&Message::Leave, self.host);
+        let _ = self.link.flush_all();
+    }
+}
+
+fn parse_packet(packet: &[u8]) -> Option<Message> {
+    let text = std::str::from_utf8(packet).ok()?;
+    // Anything that doesn't parse came from something else, or is too new or too old
+    Message::from_text(text).ok()
+}
+
+impl Message {
+    pub fn to_text(&self) -> String {
+        match self {
+            Message::Join => "join\n".to_string(),
+            Message::Leave => "leave\n".to_string(),
+            Message::Welcome(replay) => format!("welcome\n{}", replay.to_text()),
+            Message::Input(input) => {
+                let mut s = "input\n".to_string();
+                s += &format!("presses {} {}\n", input.serves, input.pauses);
+                s += &format!("analog {} {}\n", input.analog.left, input.analog.right);
+                s += "held";
+                for a in input.held.iter() {
+                    s += &format!(" {}", a.name());
+                }
+                s += "\n";
+                s
+            }
+            Message::Inputs(window) => {
+                let mut s = "inputs\n".to_string();
+                s += &format!("first_tick {}\n", window.first_tick);
+                s += &format!("received {}\n", window.received);
+                s += &format!("simulated {}\n", window.simulated);
+                if let Option::Some((tick, checksum)) = window.checksum {
+                    s += &format!("checksum {} {}\n", tick, checksum);
+                }
+                for input in window.inputs.iter() {
+                    s += &format!("tick {} held", input.axis);
+                    for a in input.held.iter() {
+                        s += &format!(" {}", a.name());
+                    }
+                    s += " pressed";
+                    for a in input.pressed.iter() {
+                        s += &format!(" {}", a.name());
+                    }
+                    s += "\n";
+                }
+                s
+            }
+            Message::State(state) => {
+                let mut s = "state\n".to_string();
+                s += &format!("frame {}\n", state.frame);
+                s += &format!("game {}\n", game_state_text(state.state));
+                s += &format!("scores {}\n", scores_text(&state.scores));
+                for p in state.paddles.iter() {
+                    s += &format!("paddle {} {}\n", p.x, p.y);
+                }
+                for b in state.balls.iter() {
+                    s += &format!("ball {} {}\n", b.x, b.y);
+                }
+                for e in state.events.iter() {
+                    s += &format!("event {}\n", event_text(e));
+                }
+                s
+            }
+        }
+    }
+
+    pub fn from_text(text: &str) -> Result<Message, String> {
+        let mut lines = text.lines();
+        match lines.next() {
+            Option::Some("join") => Result::Ok(Message::Join),
+            Option::Some("leave") => Result::Ok(Message::Leave),
+            Option::Some("welcome") => {
+                let rest: Vec<&str> = lines.collect();
+                Replay::from_text(&rest.join("\n")).map(Message::Welcome)
+            }
+            Option::Some("input") => {
+                let mut input = RemoteInput::default();
+                for line in lines {
+                    let mut parts = line.split_whitespace();
+                    match parts.next() {
+                        Option::Some("presses") => {
+                            input.serves = parse_next(&mut parts)?;
+                            input.pauses = parse_next(&mut parts)?;
+                        }
+                        Option::Some("analog") => {
+                            input.analog.left = parse_axis(&mut parts)?;
+                            input.analog.right = parse_axis(&mut parts)?;
+                        }
+                        Option::Some("held") => {
+                            for name in parts {
+                                input.held.push(
+                                    Action::from_name(name)
+                                        .ok_or(format!("unknown action {}", name))?,
+                                );
+                            }
+                        }
+                        Option::Some(other) => {
+                            return Result::Err(format!("unknown entry {}", other))
+                        }
+                        Option::None => {}
+                    }
+                }
+                Result::Ok(Message::Input(input))
+            }
+            Option::Some("inputs") => {
+                let mut window = InputWindow {
+                    first_tick: 0,
+                    inputs: Vec::new(),
+                    received: 0,
+                    simulated: 0,
+                    checksum: Option::None,
+                };
+                for line in lines {
+                    let mut parts = line.split_whitespace();
+                    match parts.next() {
+                        Option::Some("first_tick") => window.first_tick = parse_next(&mut parts)?,
+                        Option::Some("received") => window.received = parse_next(&mut parts)?,
+                        Option::Some("simulated") => window.simulated = parse_next(&mut parts)?,
+                        Option::Some("checksum") => {
+                            window.checksum =
+                                Option::Some((parse_next(&mut parts)?, parse_next(&mut parts)?));
+                        }
+                        Option::Some("tick") => window.inputs.push(parse_tick_input(&mut parts)?),
+                        Option::Some(other) => {
+                            return Result::Err(format!("unknown entry {}", other))
+                        }
+                        Option::None => {}
+                    }
+                }
+                Result::Ok(Message::Inputs(window))
+            }
+            Option::Some("state") => {
+                let mut state = NetState {
+                    frame: 0,
+                    state: GameState::Title,
+                    scores: ScoreboardData::new(),
+                    paddles: Vec::new(),
+                    balls: Vec::new(),
+                    events: Vec::new(),
+                };
+                for line in lines {
+                    let mut parts = line.split_whitespace();
+                    match parts.next() {
+                        Option::Some("frame") => state.frame = parse_next(&mut parts)?,
+                        Option::Some("game") => state.state = parse_game_state(&mut parts)?,
+                        Option::Some("scores") => state.scores = parse_scores(&mut parts)?,
+                        Option::Some("paddle") => state.paddles.push(parse_vec2(&mut parts)?),
+                        Option::Some("ball") => state.balls.push(parse_vec2(&mut parts)?),
+                        Option::Some("event") => state.events.push(parse_event(&mut parts)?),
+                        Option::Some(other) => {
+                            return Result::Err(format!("unknown entry {}", other))
+                        }
+                        Option::None => {}
+                    }
+                }
+                Result::Ok(Message::State(state))
+            }
+            Option::Some(other) => Result::Err(format!("unknown message {}", other)),
+            Option::None => Result::Err("empty message".to_string()),
+        }
+    }
+}
+
+/// Reads `<axis> held <actions> pressed <actions>`.
+fn parse_tick_input<'a, I>(parts: &mut I) -> Result<TickInput, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut input = TickInput {
+        axis: parse_axis(parts)?,
+        ..TickInput::default()
+    };
+    let mut pressed = false;
+    for word in parts {
+        match word {
+            "held" => pressed = false,
+            "pressed" => pressed = true,
+            name => {
+                let action = Action::from_name(name).ok_or(format!("unknown action {}", name))?;
+                if pressed {
+                    input.pressed.push(action);
+                } else {
+                    input.held.push(action);
+                }
+            }
+        }
+    }
+    Result::Ok(input)
+}
+
+fn event_text(event: &GameEvent) -> String {
+    let ball = |name: &str, p: &Vec2, v: &Vec2| format!("{} {} {} {} {}", name, p.x, p.y, v.x, v.y);
+    match event {
+        GameEvent::Serve {
+            towards,
+            position,
+            velocity,
+        } => ball(
+            &format!("serve {}", side_name(*towards)),
+            position,
+            velocity,
+        ),
+        GameEvent::BallHitPaddle { position, velocity } => ball("hit_paddle", position, velocity),
+        GameEvent::BallHitWall { position, velocity } => ball("hit_wall", position, velocity),
+        GameEvent::BallHitBall { position, velocity } => ball("hit_ball", position, velocity),
+        GameEvent::PointScored { scorer, scores } => {
+            format!("point {} {}", side_name(*scorer), scores_text(scores))
+        }
+        GameEvent::MatchWon { winner, scores } => {
+            format!("match {} {}", side_name(*winner), scores_text(scores))
+        }
+    }
+}
+
+fn parse_event<'a, I>(parts: &mut I) -> Result<GameEvent, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    match parts.next() {
+        Option::Some("serve") => Result::Ok(GameEvent::Serve {
+            towards: parse_side(parts)?,
+            position: parse_vec2(parts)?,
+            velocity: parse_vec2(parts)?,
+        }),
+        Option::Some("hit_paddle") => Result::Ok(GameEvent::BallHitPaddle {
+            position: parse_vec2(parts)?,
+            velocity: parse_vec2(parts)?,
+        }),
+        Option::Some("hit_wall") => Result::Ok(GameEvent::BallHitWall {
+            position: parse_vec2(parts)?,
+            velocity: parse_vec2(parts)?,
+        }),
+        Option::Some("hit_ball") => Result::Ok(GameEvent::BallHitBall {
+            position: parse_vec2(parts)?,
+            velocity: parse_vec2(parts)?,
+        }),
+        Option::Some("point") => Result::Ok(GameEvent::PointScored {
+            scorer: parse_side(parts)?,
+            scores: parse_scores(parts)?,
+        }),
+        Option::Some("match") => Result::Ok(GameEvent::MatchWon {
+            winner: parse_side(parts)?,
+            scores: parse_scores(parts)?,
+        }),
+        Option::Some(other) => Result::Err(format!("unknown event {}", other)),
+        Option::None => Result::Err("missing value".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::ai::Difficulty;
+    use crate::world::GameSettings;
+
+    #[test]
+    fn client_plays_a_match_hosted_over_loopback() {
+        let settings = GameSettings {
+            left_ai: Option::Some(Difficulty::Easy),
+            remote: Option::Some(CLIENT_SIDE),
+            ..GameSettings::default()
+        };
+        let mut host_game = Game::new(World::new(11, settings));
+        let mut host = Host::bind(0, host_game.world()).unwrap();
+        let mut client = Client::connect(&format!("127.0.0.1:{}", host.port().unwrap())).unwrap();
+        let mut welcome = Option::None;
+        for _ in 0..100 {
+            host.poll().unwrap();
+            welcome = client.wait_for_welcome(Duration::from_millis(10)).ok();
+            if welcome.is_some() {
+                break;
+            }
+        }
+        let welcome = welcome.unwrap();
+        assert_eq!((welcome.seed, welcome.settings), (11, settings));
+        assert!(host.connected());
+
+        let mut client_game = Game::new(World::new(welcome.seed, welcome.settings));
+        let start = NetState::capture(&host_game).paddles[1];
+        // The client steers with its own left keys, which move the right paddle
+        let held: HashSet<Action> = [Action::LeftUp].into_iter().collect();
+        client.press(Action::Serve);
+        let mut sent = Vec::new();
+        let mut received = 0;
+        for _ in 0..120 {
+            client.send_input(&held, AnalogInput::default()).unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+            for action in host.poll().unwrap() {
+                host_game.press(action);
+            }
+            host.set_input(
+                host_game.world_mut(),
+                &HashSet::new(),
+                AnalogInput::default(),
+            );
+            host_game.step(Duration::from_micros(16_667));
+            sent.push(NetState::capture(&host_game));
+            host.send_state(NetState::capture(&host_game)).unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+
+            if let Option::Some(state) = client.poll().unwrap() {
+                state.apply(&mut client_game);
+                let shown = NetState::capture(&client_game);
+                let host_state = &sent[state.frame as usize];
+                assert_eq!(shown.paddles, host_state.paddles);
+                assert_eq!(shown.balls, host_state.balls);
+                assert_eq!(shown.state, host_state.state);
+                received += 1;
+            }
+        }
+        assert!(received > 0);
+        assert_eq!(client_game.state(), GameState::Playing);
+        assert!(NetState::capture(&client_game).paddles[1].y < start.y);
+
+        drop(host);
+        for _ in 0..100 {
+            if client.host_left() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+            client.poll().unwrap();
+        }
+        assert!(client.host_left());
+        assert_eq!(client.poll(), Result::Ok(Option::None));
+    }
+
+    #[test]
+    fn non_finite_axes_are_refused() {
+        assert!(Message::from_text("input\nanalog 0.5 -1\n").is_ok());
+        assert!(Message::from_text("input\nanalog NaN 0\n").is_err());
+        assert!(Message::from_text("input\nanalog 0 inf\n").is_err());
+        assert!(Message::from_text("inputs\ntick NaN held\n").is_err());
+        assert!(Message::from_text("inputs\ntick -inf held\n").is_err());
+    }
+}
@@ -0,0 +1,94 @@
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 255 }
+    }
+}
+
+/// Drawing primitives the actors use, so they do not depend on a specific backend.
+pub trait Renderer {
+    fn clear(&mut self, color: Color) -> Result<(), String>;
+    fn fill_rect(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        color: Color,
+    ) -> Result<(), String>;
+    fn draw_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: Color)
+        -> Result<(), String>;
+    /// Draws `text` centered on the given point.
+    fn draw_text(
+        &mut self,
+        text: &str,
+        center_x: i32,
+        center_y: i32,
+        color: Color,
+    ) -> Result<(), String>;
+    fn present(&mut self) -> Result<(), String>;
+
+    fn fill_circle(
+        &mut self,
+        center_x: i32,
+        center_y: i32,
+        radius: i32,
+        color: Color,
+    ) -> Result<(), String> {
+        filled_circle_spans(center_x, center_y, radius, |x1, x2, y| {
+            self.draw_line(x1, y, x2, y, color)
+        })
+    }
+}
+
+// Midpoint circle algorithm, adapted from https://stackoverflow.com/a/48291620, modified to draw a filled circle.
+// Calls `span` with the endpoints and row of every horizontal line that makes up the circle.
+pub fn filled_circle_spans<F>(
+    center_x: i32,
+    center_y: i32,
+    radius: i32,
+    mut span: F,
+) -> Result<(), String>
+where
+    F: FnMut(i32, i32, i32) -> Result<(), String>,
+{
+    let diameter = radius * 2;
+
+    let mut x = radius - 1;
+    let mut y = 0;
+    let mut tx = 1;
+    let mut ty = 1;
+    let mut error = tx - diameter;
+
+    while x >= y {
+        span(center_x + x, center_x - x, center_y + y)?;
+        span(center_x + x, center_x - x, center_y - y)?;
+
+        if error <= 0 {
+            y += 1;
+            error += ty;
+            ty += 2;
+        }
+
+        if error > 0 {
+            span(center_x + y, center_x - y, center_y + x)?;
+            span(center_x + y, center_x - y, center_y - x)?;
+
+            x -= 1;
+            tx += 2;
+            error += tx - diameter;
+        }
+    }
+
+    Result::Ok(())
+}
@@ -0,0 +1,2 @@
+pub mod sdl;
+pub mod software;
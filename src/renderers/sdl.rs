@@ -0,0 +1,91 @@
+use sdl2::{
+    pixels,
+    rect::{Point, Rect},
+    render::{Canvas, TextureQuery},
+    ttf::Font,
+    video::Window,
+};
+
+use crate::renderer::{Color, Renderer};
+
+/// Draws to an SDL window. Text is rendered with the given TTF font.
+pub struct SdlRenderer<'a> {
+    canvas: Canvas<Window>,
+    font: &'a Font<'a, 'a>,
+}
+
+impl<'a> SdlRenderer<'a> {
+    pub fn new(canvas: Canvas<Window>, font: &'a Font<'a, 'a>) -> SdlRenderer<'a> {
+        SdlRenderer { canvas, font }
+    }
+}
+
+fn to_sdl_color(color: Color) -> pixels::Color {
+    pixels::Color::RGBA(color.r, color.g, color.b, color.a)
+}
+
+impl<'a> Renderer for SdlRenderer<'a> {
+    fn clear(&mut self, color: Color) -> Result<(), String> {
+        self.canvas.set_draw_color(to_sdl_color(color));
+        self.canvas.clear();
+        Result::Ok(())
+    }
+
+    fn fill_rect(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        color: Color,
+    ) -> Result<(), String> {
+        self.canvas.set_draw_color(to_sdl_color(color));
+        self.canvas.fill_rect(Rect::new(x, y, width, height))
+    }
+
+    fn draw_line(
+        &mut self,
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+        color: Color,
+    ) -> Result<(), String> {
+        self.canvas.set_draw_color(to_sdl_color(color));
+        self.canvas
+            .draw_line(Point::new(x1, y1), Point::new(x2, y2))
+    }
+
+    fn draw_text(
+        &mut self,
+        text: &str,
+        center_x: i32,
+        center_y: i32,
+        color: Color,
+    ) -> Result<(), String> {
+        let texture_creator = self.canvas.texture_creator();
+        let surface = self
+            .font
+            .render(text)
+            .blended(to_sdl_color(color))
+            .map_err(|e| e.to_string())?;
+        let tex = texture_creator
+            .create_texture_from_surface(surface)
+            .map_err(|e| e.to_string())?;
+
+        let TextureQuery { width, height, .. } = tex.query();
+        let bounds = Rect::new(
+            (center_x as f32 - width as f32 / 2.0).round() as i32,
+            (center_y as f32 - height as f32 / 2.0).round() as i32,
+            width,
+            height,
+        );
+
+        self.canvas.copy(&tex, None, bounds)
+    }
+
+    fn present(&mut self) -> Result<(), String> {
+        self.canvas.present();
+        Result::Ok(())
+    }
+}
@@ -0,0 +1,379 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+
+use crate::renderer::{Color, Renderer};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ImageFormat {
+    Png,
+    Ppm,
+}
+
+impl ImageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Ppm => "ppm",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<ImageFormat> {
+        match extension {
+            "png" => Option::Some(ImageFormat::Png),
+            "ppm" => Option::Some(ImageFormat::Ppm),
+            _ => Option::None,
+        }
+    }
+}
+
+struct FrameDump {
+    dir: PathBuf,
+    format: ImageFormat,
+}
+
+/// Rasterizes on the CPU into an RGBA buffer, without needing a window or GPU.
+/// Each `present` writes the frame to disk when a dump directory is set.
+pub struct SoftwareRenderer {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+    font: Option<(FontVec, PxScale)>,
+    dump: Option<FrameDump>,
+    frame: u64,
+}
+
+impl SoftwareRenderer {
+    /// Creates a renderer that ignores `draw_text` until a font is loaded.
+    pub fn new(width: u32, height: u32) -> SoftwareRenderer {
+        SoftwareRenderer {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+            font: Option::None,
+            dump: Option::None,
+            frame: 0,
+        }
+    }
+
+    /// Loads a TTF font and scales it so an em is `size` pixels, matching SDL_ttf's point size.
+    pub fn load_font(&mut self, path: &str, size: f32) -> Result<(), String> {
+        let data = fs::read(path).map_err(|e| format!("Failed to read font at {}: {}", path, e))?;
+        let font = FontVec::try_from_vec(data).map_err(|e| e.to_string())?;
+        let units_per_em = font
+            .units_per_em()
+            .ok_or("Font does not define units per em")?;
+        let scale = PxScale::from(size * font.height_unscaled() / units_per_em);
+        self.font = Option::Some((font, scale));
+        Result::Ok(())
+    }
+
+    /// Writes every presented frame to `dir`, creating it if needed.
+    pub fn dump_frames_to(&mut self, dir: &Path, format: ImageFormat) -> Result<(), String> {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        self.dump = Option::Some(FrameDump {
+            dir: dir.to_path_buf(),
+            format,
+        });
+        Result::Ok(())
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The current frame as tightly packed RGBA rows.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Option<Color> {
+        if x >= self.width || y >= self.height {
+            return Option::None;
+        }
+        let i = self.index(x as i32, y as i32);
+        Option::Some(Color {
+            r: self.pixels[i],
+            g: self.pixels[i + 1],
+            b: self.pixels[i + 2],
+            a: self.pixels[i + 3],
+        })
+    }
+
+    /// Number of frames presented so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn save(&self, path: &Path, format: ImageFormat) -> Result<(), String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        let mut w = BufWriter::new(file);
+        match format {
+            ImageFormat::Png => {
+                let mut encoder = png::Encoder::new(&mut w, self.width, self.height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+                writer
+                    .write_image_data(&self.pixels)
+                    .map_err(|e| e.to_string())?;
+            }
+            ImageFormat::Ppm => {
+                write!(w, "P6\n{} {}\n255\n", self.width, self.height)
+                    .map_err(|e| e.to_string())?;
+                for p in self.pixels.chunks_exact(4) {
+                    w.write_all(&p[..3]).map_err(|e| e.to_string())?;
+                }
+            }
+        }
+        w.flush().map_err(|e| e.to_string())
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+
+    fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+    }
+
+    fn blend_pixel(&mut self, x: i32, y: i32, color: Color, coverage: f32) {
+        if !self.in_bounds(x, y) {
+            return;
+        }
+        let i = self.index(x, y);
+        let alpha = coverage.clamp(0.0, 1.0) * color.a as f32 / 255.0;
+        let src = [color.r, color.g, color.b];
+        for (c, s) in src.iter().enumerate() {
+            let dst = self.pixels[i + c] as f32;
+            self.pixels[i + c] = (dst + (*s as f32 - dst) * alpha).round() as u8;
+        }
+        let dst_a = self.pixels[i + 3] as f32;
+        self.pixels[i + 3] = (dst_a + (255.0 - dst_a) * alpha).round() as u8;
+    }
+
+    fn fill_span(&mut self, x1: i32, x2: i32, y: i32, color: Color) {
+        if y < 0 || y as u32 >= self.height {
+            return;
+        }
+        let start = x1.min(x2).max(0);
+        let end = x1.max(x2).min(self.width as i32 - 1);
+        let mut x = start;
+        while x <= end {
+            self.blend_pixel(x, y, color, 1.0);
+            x += 1;
+        }
+    }
+}
+
+impl Renderer for SoftwareRenderer {
+    fn clear(&mut self, color: Color) -> Result<(), String> {
+        for p in self.pixels.chunks_exact_mut(4) {
+            p.copy_from_slice(&[color.r, color.g, color.b, color.a]);
+        }
+        Result::Ok(())
+    }
+
+    fn fill_rect(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        color: Color,
+    ) -> Result<(), String> {
+        let mut row = y;
+        while row < y + height as i32 {
+            if width > 0 {
+                self.fill_span(x, x + width as i32 - 1, row, color);
+            }
+            row += 1;
+        }
+        Result::Ok(())
+    }
+
+    // Bresenham's line algorithm
+    fn draw_line(
+        &mut self,
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+        color: Color,
+    ) -> Result<(), String> {
+        if y1 == y2 {
+            self.fill_span(x1, x2, y1, color);
+            return Result::Ok(());
+        }
+
+        let dx = (x2 - x1).abs();
+        let dy = -(y2 - y1).abs();
+        let step_x = if x1 < x2 { 1 } else { -1 };
+        let step_y = if y1 < y2 { 1 } else { -1 };
+        let mut error = dx + dy;
+        let mut x = x1;
+        let mut y = y1;
+
+        loop {
+            self.blend_pixel(x, y, color, 1.0);
+            if x == x2 && y == y2 {
+                break;
+            }
+            let e2 = 2 * error;
+            if e2 >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if e2 <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+        Result::Ok(())
+    }
+
+    fn draw_text(
+        &mut self,
+        text: &str,
+        center_x: i32,
+        center_y: i32,
+        color: Color,
+    ) -> Result<(), String> {
+        let (font, scale) = match &self.font {
+            Option::Some((f, s)) => (f, *s),
+            Option::None => return Result::Ok(()),
+        };
+        let font = font.as_scaled(scale);
+
+        let mut glyphs = Vec::new();
+        let mut caret = 0.0;
+        let mut last = Option::None;
+        for c in text.chars() {
+            let id = font.glyph_id(c);
+            if let Option::Some(prev) = last {
+                caret += font.kern(prev, id);
+            }
+            glyphs.push(id.with_scale_and_position(scale, point(caret, font.ascent())));
+            caret += font.h_advance(id);
+            last = Option::Some(id);
+        }
+
+        let left = (center_x as f32 - caret / 2.0).round() as i32;
+        let top = (center_y as f32 - font.height() / 2.0).round() as i32;
+
+        let mut coverage = Vec::new();
+        for glyph in glyphs {
+            if let Option::Some(outlined) = font.outline_glyph(glyph) {
+                let bounds = outlined.px_bounds();
+                outlined.draw(|x, y, c| {
+                    coverage.push((
+                        left + bounds.min.x as i32 + x as i32,
+                        top + bounds.min.y as i32 + y as i32,
+                        c,
+                    ));
+                });
+            }
+        }
+        for (x, y, c) in coverage {
+            self.blend_pixel(x, y, color, c);
+        }
+        Result::Ok(())
+    }
+
+    fn present(&mut self) -> Result<(), String> {
+        if let Option::Some(dump) = &self.dump {
+            let path = dump.dir.join(format!(
+                "frame_{:06}.{}",
+                self.frame,
+                dump.format.extension()
+            ));
+            self.save(&path, dump.format)?;
+        }
+        self.frame += 1;
+        Result::Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::actor::{Actor, Vec2};
+    use crate::actors::ball::Ball;
+    use crate::actors::player_paddle::PlayerPaddle;
+    use crate::controllers::local::LocalController;
+    use crate::math_helper::GameRng;
+    use crate::world::GameSettings;
+
+    #[test]
+    fn draws_a_ball_and_a_paddle() {
+        let mut renderer = SoftwareRenderer::new(120, 80);
+        renderer.clear(Color::BLACK).unwrap();
+        let paddle = PlayerPaddle::new(
+            &Vec2 { x: 15.0, y: 40.0 },
+            Box::new(LocalController::left()),
+        );
+        paddle.draw(&mut renderer).unwrap();
+        let mut rng = GameRng::seed_from_u64(0);
+        let ball = Ball::new(
+            &Vec2 { x: 80.0, y: 40.0 },
+            &GameSettings::default(),
+            &mut rng,
+        );
+        ball.draw(&mut renderer).unwrap();
+
+        // The paddle is 15 wide and 50 high around its position
+        assert_eq!(renderer.pixel(15, 40), Option::Some(Color::WHITE));
+        assert_eq!(renderer.pixel(8, 15), Option::Some(Color::WHITE));
+        assert_eq!(renderer.pixel(22, 64), Option::Some(Color::WHITE));
+        assert_eq!(renderer.pixel(7, 40), Option::Some(Color::BLACK));
+        assert_eq!(renderer.pixel(23, 40), Option::Some(Color::BLACK));
+        assert_eq!(renderer.pixel(15, 14), Option::Some(Color::BLACK));
+        assert_eq!(renderer.pixel(15, 65), Option::Some(Color::BLACK));
+
+        // The ball is round, with a radius of 23
+        assert_eq!(renderer.pixel(80, 40), Option::Some(Color::WHITE));
+        assert_eq!(renderer.pixel(60, 40), Option::Some(Color::WHITE));
+        assert_eq!(renderer.pixel(80, 20), Option::Some(Color::WHITE));
+        assert_eq!(renderer.pixel(104, 40), Option::Some(Color::BLACK));
+        assert_eq!(renderer.pixel(80, 64), Option::Some(Color::BLACK));
+        assert_eq!(renderer.pixel(97, 23), Option::Some(Color::BLACK));
+
+        assert_eq!(renderer.pixel(120, 0), Option::None);
+        let white = renderer
+            .pixels()
+            .chunks_exact(4)
+            .filter(|p| *p == [255, 255, 255, 255])
+            .count();
+        let ball_area = std::f32::consts::PI * 23.0 * 23.0;
+        assert!((white as f32 - (15.0 * 50.0 + ball_area)).abs() < 100.0);
+    }
+
+    #[test]
+    fn saves_frames_as_ppm() {
+        let mut renderer = SoftwareRenderer::new(3, 2);
+        renderer.clear(Color::rgb(10, 20, 30)).unwrap();
+        renderer.fill_rect(1, 1, 1, 1, Color::WHITE).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("pong-ppm-{}", std::process::id()));
+        renderer.dump_frames_to(&dir, ImageFormat::Ppm).unwrap();
+        renderer.present().unwrap();
+        let data = fs::read(dir.join("frame_000000.ppm")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let header = b"P6\n3 2\n255\n";
+        assert_eq!(&data[..header.len()], header);
+        let mut body = [10, 20, 30].repeat(6);
+        body[12..15].copy_from_slice(&[255, 255, 255]);
+        assert_eq!(&data[header.len()..], &body[..]);
+        assert_eq!(renderer.frame(), 1);
+    }
+}
@@ -0,0 +1,540 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fs;
+use std::hash::{Hash, Hasher};
+
+use crate::controllers::ai::Difficulty;
+use crate::controllers::local::Side;
+use crate::input::{Action, AnalogInput};
+use crate::rules::{ServeDirection, Server};
+use crate::snapshot::{parse_side, side_name};
+use crate::world::{GameSettings, FIXED_UPDATE_RATE, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub const REPLAY_VERSION: u32 = 7;
+const REPLAY_MAGIC: &str = "pong-replay";
+
+/// The input from `tick` onwards, until the next change.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct InputChange {
+    pub tick: u64,
+    pub actions: Vec<Action>,
+    pub analog: AnalogInput,
+    /// How the remote player's paddle moves, if the match has one
+    pub remote: f32,
+}
+
+/// Everything needed to play a match back exactly: the seed, the settings the
+/// simulation ran with and the input of every fixed update.
+///
+/// Stored as text, one `key value` setting per line followed by one line per
+/// input change, e.g. `input 120 left_up right_down` for both actions held from tick 120.
+/// Analog input is added as `left_axis=<value>` and `right_axis=<value>` when not zero,
+/// and the remote player's intent as `remote=<value>`.
+/// The match rules, ball tuning and multi-ball settings are stored one per line,
+/// e.g. `server alternate 2`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Replay {
+    pub seed: u64,
+    pub fixed_update_rate: f32,
+    pub screen_width: i16,
+    pub screen_height: i16,
+    pub settings: GameSettings,
+    /// Number of fixed updates that were recorded.
+    pub ticks: u64,
+    pub inputs: Vec<InputChange>,
+    /// A hash of every input change up to and including the one at the same index
+    history: Vec<u64>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, settings: GameSettings) -> Replay {
+        Replay {
+            seed,
+            fixed_update_rate: FIXED_UPDATE_RATE,
+            screen_width: SCREEN_WIDTH,
+            screen_height: SCREEN_HEIGHT,
+            settings,
+            ticks: 0,
+            inputs: Vec::new(),
+            history: Vec::new(),
+        }
+    }
+
+    /// Records the input of the next fixed update.
+    pub fn record(&mut self, actions_pressed: &HashSet<Action>, analog: AnalogInput, remote: f32) {
+        let mut actions: Vec<Action> = actions_pressed.iter().copied().collect();
+        actions.sort_unstable_by_key(|a| a.name());
+
+        let changed = match self.inputs.last() {
+            Option::Some(last) => {
+                last.actions != actions || last.analog != analog || last.remote != remote
+            }
+            Option::None => {
+                !actions.is_empty() || analog != AnalogInput::default() || remote != 0.0
+            }
+        };
+        if changed {
+            self.push(InputChange {
+                tick: self.ticks,
+                actions,
+                analog,
+                remote,
+            });
+        }
+        self.ticks += 1;
+    }
+
+    fn push(&mut self, change: InputChange) {
+        let mut hasher = DefaultHasher::new();
+        self.history.last().hash(&mut hasher);
+        change.tick.hash(&mut hasher);
+        change.actions.hash(&mut hasher);
+        change.analog.left.to_bits().hash(&mut hasher);
+        change.analog.right.to_bits().hash(&mut hasher);
+        change.remote.to_bits().hash(&mut hasher);
+        self.history.push(hasher.finish());
+        self.inputs.push(change);
+    }
+
+    /// A hash of the input of the first `ticks` fixed updates, equal for two recordings
+    /// only if they had the same input up to there.
+    pub fn history_checksum(&self, ticks: u64) -> u64 {
+        let changes = self.inputs.partition_point(|c| c.tick < ticks);
+        let mut hasher = DefaultHasher::new();
+        changes
+            .checked_sub(1)
+            .map(|i| self.history[i])
+            .hash(&mut hasher);
+        ticks.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Forgets every fixed update from `ticks` onwards, e.g. after going back to a snapshot.
+    pub fn truncate(&mut self, ticks: u64) {
+        if ticks < self.ticks {
+            let changes = self.inputs.partition_point(|c| c.tick < ticks);
+            self.inputs.truncate(changes);
+            self.history.truncate(changes);
+            self.ticks = ticks;
+        }
+    }
+
+    /// The input of fixed update `tick`.
+    pub fn input_at(&self, tick: u64) -> InputChange {
+        let i = self.inputs.partition_point(|c| c.tick <= tick);
+        match i.checked_sub(1).and_then(|i| self.inputs.get(i)) {
+            Option::Some(change) => change.clone(),
+            Option::None => InputChange::default(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut s = format!("{} {}\n", REPLAY_MAGIC, REPLAY_VERSION);
+        s += &format!("seed {}\n", self.seed);
+        s += &format!("fixed_update_rate {}\n", self.fixed_update_rate);
+        s += &format!("screen {} {}\n", self.screen_width, self.screen_height);
+        s += &settings_to_text(&self.settings);
+        s += &format!("ticks {}\n", self.ticks);
+        for change in self.inputs.iter() {
+            s += &format!("input {}", change.tick);
+            for a in change.actions.iter() {
+                s += &format!(" {}", a.name());
+            }
+            if change.analog.left != 0.0 {
+                s += &format!(" left_axis={}", change.analog.left);
+            }
+            if change.analog.right != 0.0 {
+                s += &format!(" right_axis={}", change.analog.right);
+            }
+            if change.remote != 0.0 {
+                s += &format!(" remote={}", change.remote);
+            }
+            s += "\n";
+        }
+        s
+    }
+
+    pub fn from_text(text: &str) -> Result<Replay, String> {
+        let mut lines = text.lines().enumerate();
+
+        let version = match lines.next() {
+            Option::Some((_, header)) => match header.split_once(' ') {
+                Option::Some((REPLAY_MAGIC, version)) => version
+                    .parse::<u32>()
+                    .map_err(|e| format!("Invalid replay version: {}", e))?,
+                _ => return Result::Err("Not a replay file".to_string()),
+            },
+            Option::None => return Result::Err("Replay file is empty".to_string()),
+        };
+        if version != REPLAY_VERSION {
+            return Result::Err(format!(
+                "Unsupported replay version {}, expected {}",
+                version, REPLAY_VERSION
+            ));
+        }
+
+        let mut replay = Replay::new(0, GameSettings::default());
+        for (n, line) in lines {
+            let err = |e: &dyn std::fmt::Display| format!("Replay line {}: {}", n + 1, e);
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Option::Some("seed") => {
+                    replay.seed = parse_next(&mut parts).map_err(|e| err(&e))?;
+                }
+                Option::Some("fixed_update_rate") => {
+                    replay.fixed_update_rate = parse_next(&mut parts).map_err(|e| err(&e))?;
+                }
+                Option::Some("screen") => {
+                    replay.screen_width = parse_next(&mut parts).map_err(|e| err(&e))?;
+                    replay.screen_height = parse_next(&mut parts).map_err(|e| err(&e))?;
+                }
+                Option::Some("ticks") => {
+                    replay.ticks = parse_next(&mut parts).map_err(|e| err(&e))?;
+                }
+                Option::Some("input") => {
+                    let tick = parse_next(&mut parts).map_err(|e| err(&e))?;
+                    let mut change = InputChange {
+                        tick,
+                        ..InputChange::default()
+                    };
+                    for token in parts {
+                        match token.split_once('=') {
+                            Option::Some(("left_axis", v)) => {
+                                change.analog.left =
+                                    parse_axis(&mut std::iter::once(v)).map_err(|e| err(&e))?;
+                            }
+                            Option::Some(("right_axis", v)) => {
+                                change.analog.right =
+                                    parse_axis(&mut std::iter::once(v)).map_err(|e| err(&e))?;
+                            }
+                            Option::Some(("remote", v)) => {
+                                change.remote =
+                                    parse_axis(&mut std::iter::once(v)).map_err(|e| err(&e))?;
+                            }
+                            _ => change.actions.push(
+                                Action::from_name(token)
+                                    .ok_or_else(|| err(&format!("unknown input {}", token)))?,
+                            ),
+                        }
+                    }
+                    replay.push(change);
+                }
+                Option::Some(key) => {
+                    let known = parse_setting(key, &mut parts, &mut replay.settings)
+                        .map_err(|e| err(&e))?;
+                    if !known {
+                        return Result::Err(err(&format!("unknown entry {}", key)));
+                    }
+                }
+                Option::None => {}
+            }
+        }
+        validate_settings(&replay.settings).map_err(|e| format!("Invalid replay {}", e))?;
+        Result::Ok(replay)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_text())
+            .map_err(|e| format!("Failed to write replay to {}: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<Replay, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read replay at {}: {}", path, e))?;
+        let replay = Replay::from_text(&text)?;
+
+        if replay.fixed_update_rate != FIXED_UPDATE_RATE
+            || replay.screen_width != SCREEN_WIDTH
+            || replay.screen_height != SCREEN_HEIGHT
+        {
+            return Result::Err(format!(
+                "Replay at {} was recorded with different game settings",
+                path
+            ));
+        }
+        Result::Ok(replay)
+    }
+}
+
+/// Parses the next of the whitespace separated values of a line.
+pub fn parse_next<'a, T, I>(parts: &mut I) -> Result<T, String>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+    I: Iterator<Item = &'a str>,
+{
+    parts
+        .next()
+        .ok_or("missing value".to_string())?
+        .parse::<T>()
+        .map_err(|e| e.to_string())
+}
+
+/// Parses an analog axis, refusing values like `NaN` and `inf` that would stick to a paddle.
+pub fn parse_axis<'a, I>(parts: &mut I) -> Result<f32, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let axis: f32 = parse_next(parts)?;
+    if !axis.is_finite() {
+        return Result::Err(format!("invalid axis {}", axis));
+    }
+    Result::Ok(axis)
+}
+
+/// The settings as one `key value` line each, the way replays and snapshots store them.
+pub fn settings_to_text(settings: &GameSettings) -> String {
+    let mut s = String::new();
+    s += &format!("left_ai {}\n", ai_name(settings.left_ai));
+    s += &format!("right_ai {}\n", ai_name(settings.right_ai));
+    match settings.remote {
+        Option::Some(side) => s += &format!("remote {}\n", side_name(side)),
+        Option::None => s += "remote none\n",
+    }
+    let rules = &settings.rules;
+    s += &format!("points_to_win {}\n", rules.points_to_win);
+    s += &format!("win_by {}\n", rules.win_by);
+    s += &format!("best_of {}\n", rules.best_of);
+    match rules.server {
+        Server::Alternate { every } => s += &format!("server alternate {}\n", every),
+        server => s += &format!("server {}\n", server.name()),
+    }
+    s += &format!("serve_direction {}\n", rules.serve_direction.name());
+    s += &format!("serve_delay {}\n", rules.serve_delay);
+    s += &format!("max_bounce_angle {}\n", settings.ball.max_bounce_angle);
+    s += &format!("paddle_influence {}\n", settings.ball.paddle_influence);
+    s += &format!("initial_speed {}\n", settings.ball.initial_speed);
+    s += &format!("max_speed {}\n", settings.ball.max_speed);
+    s += &format!("paddle_speed_up {}\n", settings.ball.paddle_speed_up);
+    s += &format!("wall_speed_up {}\n", settings.ball.wall_speed_up);
+    s += &format!("rally_speed_up {}\n", settings.ball.rally_speed_up);
+    s += &format!("rally_exponent {}\n", settings.ball.rally_exponent);
+    s += &format!(
+        "speed_up_per_second {}\n",
+        settings.ball.speed_up_per_second
+    );
+    s += &format!("max_balls {}\n", settings.multi_ball.max_balls);
+    s += &format!("spawn_interval {}\n", settings.multi_ball.spawn_interval);
+    s += &format!(
+        "spawn_every_returns {}\n",
+        settings.multi_ball.spawn_every_returns
+    );
+    s
+}
+
+/// Reads a line written by `settings_to_text` into `settings`, given its first word as
+/// `key`. Returns false if the line isn't a setting.
+pub fn parse_setting<'a, I>(
+    key: &str,
+    parts: &mut I,
+    settings: &mut GameSettings,
+) -> Result<bool, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    match key {
+        "left_ai" => {
+            settings.left_ai = parse_ai(parts)?;
+        }
+        "right_ai" => {
+            settings.right_ai = parse_ai(parts)?;
+        }
+        "remote" => {
+            settings.remote = parse_remote(parts)?;
+        }
+        "points_to_win" => {
+            settings.rules.points_to_win = parse_next(parts)?;
+        }
+        "win_by" => {
+            settings.rules.win_by = parse_next(parts)?;
+        }
+        "best_of" => {
+            settings.rules.best_of = parse_next(parts)?;
+        }
+        "server" => {
+            let name: String = parse_next(parts)?;
+            let every = match name.as_str() {
+                "alternate" => parse_next(parts)?,
+                _ => 1,
+            };
+            settings.rules.server =
+                Server::from_name(&name, every).ok_or(format!("unknown server {}", name))?;
+        }
+        "serve_direction" => {
+            let name: String = parse_next(parts)?;
+            settings.rules.serve_direction = ServeDirection::from_name(&name)
+                .ok_or(format!("unknown serve direction {}", name))?;
+        }
+        "serve_delay" => {
+            settings.rules.serve_delay = parse_next(parts)?;
+        }
+        "max_bounce_angle" => {
+            settings.ball.max_bounce_angle = parse_next(parts)?;
+        }
+        "paddle_influence" => {
+            settings.ball.paddle_influence = parse_next(parts)?;
+        }
+        "initial_speed" => {
+            settings.ball.initial_speed = parse_next(parts)?;
+        }
+        "max_speed" => {
+            settings.ball.max_speed = parse_next(parts)?;
+        }
+        "paddle_speed_up" => {
+            settings.ball.paddle_speed_up = parse_next(parts)?;
+        }
+        "wall_speed_up" => {
+            settings.ball.wall_speed_up = parse_next(parts)?;
+        }
+        "rally_speed_up" => {
+            settings.ball.rally_speed_up = parse_next(parts)?;
+        }
+        "rally_exponent" => {
+            settings.ball.rally_exponent = parse_next(parts)?;
+        }
+        "speed_up_per_second" => {
+            settings.ball.speed_up_per_second = parse_next(parts)?;
+        }
+        "max_balls" => {
+            settings.multi_ball.max_balls = parse_next(parts)?;
+        }
+        "spawn_interval" => {
+            settings.multi_ball.spawn_interval = parse_next(parts)?;
+        }
+        "spawn_every_returns" => {
+            settings.multi_ball.spawn_every_returns = parse_next(parts)?;
+        }
+        _ => return Result::Ok(false),
+    }
+    Result::Ok(true)
+}
+
+/// Checks settings read from a file, naming the part that is wrong.
+pub fn validate_settings(settings: &GameSettings) -> Result<(), String> {
+    settings
+        .rules
+        .validate()
+        .map_err(|e| format!("rules: {}", e))?;
+    settings
+        .ball
+        .validate()
+        .map_err(|e| format!("ball tuning: {}", e))?;
+    settings
+        .multi_ball
+        .validate()
+        .map_err(|e| format!("multi-ball settings: {}", e))
+}
+
+fn ai_name(ai: Option<Difficulty>) -> &'static str {
+    match ai {
+        Option::Some(d) => d.name(),
+        Option::None => "none",
+    }
+}
+
+fn parse_remote<'a, I>(parts: &mut I) -> Result<Option<Side>, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    match parts.next() {
+        Option::Some("none") => Result::Ok(Option::None),
+        Option::Some(name) => parse_side(&mut std::iter::once(name)).map(Option::Some),
+        Option::None => Result::Err("missing value".to_string()),
+    }
+}
+
+fn parse_ai<'a, I>(parts: &mut I) -> Result<Option<Difficulty>, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    match parts.next() {
+        Option::Some("none") => Result::Ok(Option::None),
+        Option::Some(name) => Difficulty::from_name(name)
+            .map(Option::Some)
+            .ok_or(format!("unknown difficulty {}", name)),
+        Option::None => Result::Err("missing value".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::headless::HeadlessRunner;
+    use crate::world::World;
+
+    /// A left player who keeps changing keys and tilting the stick, against the computer
+    fn record_match(seed: u64, settings: GameSettings) -> (Replay, HeadlessRunner) {
+        let mut world = World::new(seed, settings);
+        world.start_recording().unwrap();
+        let mut runner = HeadlessRunner::with_world(world);
+        for frame in 0..60 * 120 {
+            let info = &mut runner.world_mut().update_info;
+            info.actions_pressed.clear();
+            match (frame / 45) % 3 {
+                0 => info.actions_pressed.insert(Action::LeftUp),
+                1 => info.actions_pressed.insert(Action::LeftDown),
+                _ => false,
+            };
+            info.analog.left = if (frame / 100) % 4 == 3 {
+                1.0 / 3.0
+            } else {
+                0.0
+            };
+            runner.step();
+        }
+        let replay = runner.world_mut().take_recording().unwrap();
+        (replay, runner)
+    }
+
+    #[test]
+    fn replay_survives_text_and_plays_back_the_same_match() {
+        let mut settings = GameSettings {
+            right_ai: Option::Some(Difficulty::Easy),
+            ..GameSettings::default()
+        };
+        settings.multi_ball.max_balls = 2;
+        settings.multi_ball.spawn_every_returns = 3;
+        let (replay, recorded) = record_match(42, settings);
+        let scores = recorded.scores().unwrap();
+        assert!(scores.points_played > 0);
+        assert!(replay.inputs.len() > 100);
+
+        let text = replay.to_text();
+        let loaded = Replay::from_text(&text).unwrap();
+        assert_eq!(loaded, replay);
+        assert_eq!(loaded.to_text(), text);
+
+        let mut playback = HeadlessRunner::with_world(World::from_replay(loaded));
+        while !playback.world().replay_finished() {
+            playback.run_for(Duration::from_secs(1));
+        }
+        assert_eq!(playback.world().fixed_updates(), replay.ticks);
+        assert_eq!(playback.scores(), Option::Some(scores));
+        assert_eq!(
+            playback.world().snapshot().actors,
+            recorded.world().snapshot().actors
+        );
+    }
+
+    #[test]
+    fn replay_text_is_checked() {
+        assert!(Replay::from_text("").is_err());
+        assert!(Replay::from_text("pong-save 1\n").is_err());
+        let old = format!("{} {}\n", REPLAY_MAGIC, REPLAY_VERSION - 1);
+        assert!(Replay::from_text(&old).is_err());
+
+        let text = Replay::new(1, GameSettings::default()).to_text();
+        let unknown = format!("{}input 3 jump\n", text);
+        assert!(Replay::from_text(&unknown).is_err());
+        let missing = format!("{}input\n", text);
+        assert!(Replay::from_text(&missing).is_err());
+        for token in ["left_axis=NaN", "right_axis=inf", "remote=-inf"] {
+            let bad_axis = format!("{}input 3 {}\n", text, token);
+            assert!(Replay::from_text(&bad_axis).is_err());
+        }
+        let axis = format!("{}input 3 left_axis=0.5\n", text);
+        assert!(Replay::from_text(&axis).is_ok());
+    }
+}
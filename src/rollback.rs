@@ -0,0 +1,563 @@
+use std::collections::{HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::controllers::local::Side;
+use crate::event::GameEvent;
+use crate::game::{Game, GameSnapshot};
+use crate::input::{Action, AnalogInput};
+use crate::net::{input_for_side, Link, Message, CLIENT_SIDE, HOST_SIDE};
+use crate::replay::Replay;
+use crate::world::{World, FIXED_UPDATE_TIME_MICROS_U64};
+
+/// How many fixed updates a peer runs ahead on guessed input before waiting for the other
+const MAX_PREDICTION: u64 = 8;
+/// Inputs the other peer hasn't confirmed are sent again with every packet, up to this many
+const MAX_INPUTS_PER_PACKET: usize = 32;
+/// How many fixed updates further ahead than the other peer a peer may get before it
+/// waits a frame for them to catch up
+const MAX_ADVANTAGE: i64 = 1;
+/// Peers compare checksums of their games before every fixed update that is a multiple of this
+const CHECKSUM_INTERVAL: u64 = 30;
+/// How many of its own checksums a peer keeps to compare with the other's
+const MAX_CHECKSUMS: usize = 16;
+const JOIN_INTERVAL: Duration = Duration::from_millis(500);
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One player's input for a single fixed update.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct TickInput {
+    /// Paddle actions held, already moved onto the player's own side
+    pub held: Vec<Action>,
+    /// The player's analog axis
+    pub axis: f32,
+    /// Serves and pauses pressed since the last fixed update
+    pub pressed: Vec<Action>,
+}
+
+impl TickInput {
+    /// The input of the player at `side`, from whichever keys and sticks they used.
+    /// Presses go through `RollbackSession::press`.
+    pub fn for_side(actions: &HashSet<Action>, analog: AnalogInput, side: Side) -> TickInput {
+        let (actions, analog) = input_for_side(actions, analog, side);
+        let mut held: Vec<Action> = actions
+            .into_iter()
+            .filter(|a| {
+                matches!(
+                    a,
+                    Action::LeftUp | Action::LeftDown | Action::RightUp | Action::RightDown
+                )
+            })
+            .collect();
+        held.sort_unstable_by_key(|a| a.name());
+        TickInput {
+            held,
+            axis: analog.left + analog.right,
+            pressed: Vec::new(),
+        }
+    }
+
+    /// The guess for the other player's next input: the same keys held, nothing pressed.
+    fn predict_next(&self) -> TickInput {
+        TickInput {
+            held: self.held.clone(),
+            axis: self.axis,
+            pressed: Vec::new(),
+        }
+    }
+}
+
+/// A run of one peer's inputs starting at fixed update `first_tick`, sent again and again
+/// until the other peer has them all.
+#[derive(Clone, PartialEq, Debug)]
+pub struct InputWindow {
+    pub first_tick: u64,
+    pub inputs: Vec<TickInput>,
+    /// How many of the receiver's inputs the sender has, counted from the first
+    pub received: u64,
+    /// How many fixed updates the sender has simulated, so the receiver can tell which of
+    /// them is running ahead
+    pub simulated: u64,
+    /// The sender's newest checksum that no longer depends on a guess, with the fixed update
+    /// it was taken before
+    pub checksum: Option<(u64, u64)>,
+}
+
+/// A match between two peers that both simulate it. Each peer runs ahead on a guess of
+/// the other's input, keeps a snapshot of every fixed update that guess went into, and
+/// goes back and simulates those again when the real input turns out different.
+///
+/// Both peers have to start from a `Game` of the same seed and settings, see `welcome`,
+/// and only change it through the session.
+pub struct RollbackSession {
+    /// The seed and settings the match started with
+    welcome: Replay,
+    link: Link,
+    peer: SocketAddr,
+    side: Side,
+    /// The next fixed update to simulate
+    tick: u64,
+    local: Vec<TickInput>,
+    /// The other peer's input of every fixed update it has sent so far
+    remote: Vec<TickInput>,
+    /// The snapshot from before each fixed update simulated on a guess, and the guess
+    guesses: VecDeque<(u64, GameSnapshot, TickInput)>,
+    /// How many of our inputs the other peer has
+    peer_received: u64,
+    /// How far the other peer had simulated past our last input it had, as of the newest
+    /// window, and how far it had simulated then
+    peer_ahead: i64,
+    peer_simulated: u64,
+    /// The fixed update we last waited at for the other peer to catch up
+    waited_at: Option<u64>,
+    /// Checksums of the game before every fixed update that is a multiple of
+    /// `CHECKSUM_INTERVAL`, oldest first
+    checksums: VecDeque<(u64, u64)>,
+    /// The newest checksum the other peer sent
+    peer_checksum: Option<(u64, u64)>,
+    last_heard: Instant,
+    events: Vec<GameEvent>,
+    rollbacks: u64,
+    /// Presses made while waiting for the other peer, for the next fixed update
+    unsent_presses: Vec<Action>,
+}
+
+impl RollbackSession {
+    /// Waits for another peer to join on `link` for a match of `world`'s seed and
+    /// settings, with this peer on the left, or fails after `timeout`.
+    pub fn host(
+        mut link: Link,
+        world: &World,
+        timeout: Duration,
+    ) -> Result<RollbackSession, String> {
+        let started = Instant::now();
+        let welcome = Replay::new(world.seed(), *world.settings());
+        loop {
+            if let Option::Some((Message::Join, from)) = link.receive()? {
+                link.send_to(&Message::Welcome(welcome.clone()), from)?;
+                return Result::Ok(RollbackSession::new(link, from, HOST_SIDE, welcome));
+            }
+            if started.elapsed() > timeout {
+                return Result::Err("Nobody joined".to_string());
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Joins the peer hosting at `peer` to play on the right, or fails after `timeout`.
+    pub fn join(
+        mut link: Link,
+        peer: SocketAddr,
+        timeout: Duration,
+    ) -> Result<RollbackSession, String> {
+        let started = Instant::now();
+        let mut last_join: Option<Instant> = Option::None;
+        loop {
+            if last_join.map(|t| t.elapsed() >= JOIN_INTERVAL) != Option::Some(false) {
+                link.send_to(&Message::Join, peer)?;
+                last_join = Option::Some(Instant::now());
+            }
+            while let Option::Some((message, from)) = link.receive()? {
+                if let (Message::Welcome(welcome), true) = (message, from == peer) {
+                    return Result::Ok(RollbackSession::new(link, peer, CLIENT_SIDE, welcome));
+                }
+            }
+            if started.elapsed() > timeout {
+                return Result::Err("The host did not answer".to_string());
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn new(link: Link, peer: SocketAddr, side: Side, welcome: Replay) -> RollbackSession {
+        RollbackSession {
+            welcome,
+            link,
+            peer,
+            side,
+            tick: 0,
+            local: Vec::new(),
+            remote: Vec::new(),
+            guesses: VecDeque::new(),
+            peer_received: 0,
+            peer_ahead: 0,
+            peer_simulated: 0,
+            waited_at: Option::None,
+            checksums: VecDeque::new(),
+            peer_checksum: Option::None,
+            last_heard: Instant::now(),
+            events: Vec::new(),
+            rollbacks: 0,
+            unsent_presses: Vec::new(),
+        }
+    }
+
+    /// The seed and settings of the match.
+    pub fn welcome(&self) -> &Replay {
+        &self.welcome
+    }
+
+    /// Where the other peer plays from.
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Which paddle this peer plays.
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// The next fixed update to simulate.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// How many fixed updates have the other peer's real input.
+    pub fn confirmed(&self) -> u64 {
+        self.remote.len() as u64
+    }
+
+    /// How many times a guess was wrong and fixed updates were simulated again.
+    pub fn rollbacks(&self) -> u64 {
+        self.rollbacks
+    }
+
+    /// Takes the events of every fixed update simulated for the first time. Events of
+    /// fixed updates that are simulated again aren't repeated.
+    pub fn take_events(&mut self) -> Vec<GameEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Queues a serve or pause for the next fixed update. Anything else is ignored.
+    pub fn press(&mut self, action: Action) {
+        if action == Action::Serve || action == Action::Pause {
+            self.unsent_presses.push(action);
+        }
+    }
+
+    /// Reads the other peer's input, going back to fix any wrong guesses, then simulates the
+    /// next fixed update of `game` with `input`. Returns false if it had to wait for the
+    /// other peer instead, because it is too far behind or this peer is running ahead.
+    pub fn advance(&mut self, game: &mut Game, mut input: TickInput) -> Result<bool, String> {
+        self.receive(game)?;
+
+        self.unsent_presses.append(&mut input.pressed);
+        let advanced = self.tick < self.confirmed() + MAX_PREDICTION && !self.wait_for_peer();
+        if advanced {
+            input.pressed = std::mem::take(&mut self.unsent_presses);
+            self.local.push(input);
+            self.simulate(game, self.tick, true)?;
+            self.tick += 1;
+        }
+        self.send_inputs()?;
+        Result::Ok(advanced)
+    }
+
+    /// Whether to skip a frame so the other peer catches up. Both peers are the same number
+    /// of fixed updates ahead of the input they have from each other when they run in step,
+    /// so the difference is twice how far this one runs ahead, e.g. after starting first.
+    /// Waits at most one frame per fixed update, so a late measurement can't stall it for long.
+    fn wait_for_peer(&mut self) -> bool {
+        let ahead = self.tick as i64 - self.confirmed() as i64;
+        if (ahead - self.peer_ahead) / 2 <= MAX_ADVANTAGE
+            || self.waited_at == Option::Some(self.tick)
+        {
+            return false;
+        }
+        self.waited_at = Option::Some(self.tick);
+        true
+    }
+
+    /// Reads and sends input without simulating anything, e.g. while the window is busy.
+    pub fn poll(&mut self, game: &mut Game) -> Result<(), String> {
+        self.receive(game)?;
+        self.send_inputs()
+    }
+
+    fn receive(&mut self, game: &mut Game) -> Result<(), String> {
+        while let Option::Some((message, from)) = self.link.receive()? {
+            if from != self.peer {
+                continue;
+            }
+            self.last_heard = Instant::now();
+            match message {
+                Message::Inputs(window) => {
+                    self.peer_received = self.peer_received.max(window.received);
+                    if window.simulated >= self.peer_simulated {
+                        self.peer_simulated = window.simulated;
+                        self.peer_ahead = window.simulated as i64 - window.received as i64;
+                    }
+                    if window.checksum > self.peer_checksum {
+                        self.peer_checksum = window.checksum;
+                    }
+                    for (i, input) in window.inputs.into_iter().enumerate() {
+                        if window.first_tick + i as u64 == self.confirmed() {
+                            self.remote.push(input);
+                        }
+                    }
+                }
+                // Our welcome was lost, so the other peer is still asking
+                Message::Join if self.side == HOST_SIDE => {
+                    let welcome = Message::Welcome(self.welcome.clone());
+                    self.link.send_to(&welcome, self.peer)?;
+                }
+                Message::Leave => return Result::Err("The other player left".to_string()),
+                _ => {}
+            }
+        }
+        if self.last_heard.elapsed() > TIMEOUT {
+            return Result::Err("Lost connection to the other player".to_string());
+        }
+
+        // The first fixed update that was simulated on a wrong guess
+        let wrong = self
+            .guesses
+            .iter()
+            .find(|(t, _, guess)| {
+                self.remote
+                    .get(*t as usize)
+                    .is_some_and(|real| real != guess)
+            })
+            .map(|(t, _, _)| *t);
+        if let Option::Some(from) = wrong {
+            self.rollback(game, from)?;
+        }
+
+        let confirmed = self.confirmed();
+        while self.guesses.front().is_some_and(|(t, _, _)| *t < confirmed) {
+            self.guesses.pop_front();
+        }
+
+        // Floating point maths can come out different on another machine
+        if let Option::Some((tick, theirs)) = self.peer_checksum {
+            let ours = self.checksums.iter().find(|(t, _)| *t == tick);
+            if let Option::Some((_, ours)) = ours.filter(|_| tick <= confirmed) {
+                if *ours != theirs {
+                    return Result::Err(format!(
+                        "The game went out of sync with the other player at fixed update {}",
+                        tick
+                    ));
+                }
+            }
+        }
+        Result::Ok(())
+    }
+
+    /// Goes back to before fixed update `from` and simulates up to the current one again.
+    fn rollback(&mut self, game: &mut Game, from: u64) -> Result<(), String> {
+        let i = match self.guesses.iter().position(|(t, _, _)| *t == from) {
+            Option::Some(i) => i,
+            Option::None => return Result::Ok(()),
+        };
+        let snapshot = self.guesses[i].1.clone();
+        self.guesses.truncate(i);
+        game.rewind(&snapshot)?;
+        self.rollbacks += 1;
+
+        let mut t = from;
+        while t < self.tick {
+            self.simulate(game, t, false)?;
+            t += 1;
+        }
+        Result::Ok(())
+    }
+
+    /// Simulates fixed update `tick` with our input and the other peer's, real or guessed.
+    fn simulate(&mut self, game: &mut Game, tick: u64, first_time: bool) -> Result<(), String> {
+        if tick.is_multiple_of(CHECKSUM_INTERVAL) {
+            // Simulating again after a wrong guess replaces the checksum
+            self.checksums.retain(|(t, _)| *t < tick);
+            self.checksums.push_back((tick, game.snapshot().checksum()));
+            while self.checksums.len() > MAX_CHECKSUMS {
+                self.checksums.pop_front();
+            }
+        }
+        let remote = match self.remote.get(tick as usize) {
+            Option::Some(input) => input.clone(),
+            Option::None => {
+                let guess = match self.remote.last() {
+                    Option::Some(last) => last.predict_next(),
+                    Option::None => TickInput::default(),
+                };
+                self.guesses
+                    .push_back((tick, game.snapshot(), guess.clone()));
+                guess
+            }
+        };
+        let local = self
+            .local
+            .get(tick as usize)
+            .cloned()
+            .ok_or(format!("No input for fixed update {}", tick))?;
+        let (left, right) = match self.side {
+            Side::Left => (&local, &remote),
+            Side::Right => (&remote, &local),
+        };
+
+        let info = &mut game.world_mut().update_info;
+        info.actions_pressed = left.held.iter().chain(right.held.iter()).copied().collect();
+        info.analog = AnalogInput {
+            left: left.axis,
+            right: right.axis,
+        };
+        for action in left.pressed.iter().chain(right.pressed.iter()) {
+            game.press(*action);
+        }
+        game.step(Duration::from_micros(FIXED_UPDATE_TIME_MICROS_U64));
+        if first_time {
+            self.events.extend_from_slice(game.world().events());
+        }
+        Result::Ok(())
+    }
+
+    fn send_inputs(&mut self) -> Result<(), String> {
+        let first = self.peer_received.min(self.local.len() as u64);
+        let inputs: Vec<TickInput> = self.local[first as usize..]
+            .iter()
+            .take(MAX_INPUTS_PER_PACKET)
+            .cloned()
+            .collect();
+        let window = InputWindow {
+            first_tick: first,
+            inputs,
+            received: self.confirmed(),
+            simulated: self.tick,
+            // Only checksums of games that had the other peer's real input all along
+            checksum: self
+                .checksums
+                .iter()
+                .rev()
+                .find(|(t, _)| *t <= self.confirmed())
+                .copied(),
+        };
+        self.link.send_to(&Message::Inputs(window), self.peer)
+    }
+}
+
+impl Drop for RollbackSession {
+    fn drop(&mut self) {
+        let _ = self.link.send_to(&Message::Leave, self.peer);
+        let _ = self.link.flush_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::controllers::ai::Difficulty;
+    use crate::game::GameState;
+    use crate::net::LinkConditions;
+    use crate::rules::MatchRules;
+    use crate::world::GameSettings;
+
+    const TICKS: u64 = 300;
+
+    /// Each player moves their paddle up, down and stops on their own rhythm, so the
+    /// other's guesses keep turning out wrong
+    fn input(side: Side, tick: u64) -> TickInput {
+        let period = match side {
+            Side::Left => 40,
+            Side::Right => 23,
+        };
+        let held = match (tick / period) % 3 {
+            0 => vec![Action::LeftUp],
+            1 => vec![Action::LeftDown],
+            _ => vec![],
+        };
+        let mut input =
+            TickInput::for_side(&held.into_iter().collect(), AnalogInput::default(), side);
+        if side == Side::Left && tick == 5 {
+            input.pressed.push(Action::Serve);
+        }
+        input
+    }
+
+    fn play(mut session: RollbackSession, done: Arc<AtomicUsize>) -> (u64, u64) {
+        let welcome = session.welcome().clone();
+        let mut world = World::new(welcome.seed, welcome.settings);
+        world.start_recording().unwrap();
+        let mut game = Game::new(world);
+        while session.tick() < TICKS {
+            let input = input(session.side(), session.tick());
+            session.advance(&mut game, input).unwrap();
+            std::thread::sleep(Duration::from_millis(4));
+        }
+        while session.confirmed() < TICKS {
+            session.poll(&mut game).unwrap();
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        let checksum = game.snapshot().checksum();
+        // Keep sending input until the other peer has all of it too
+        done.fetch_add(1, Ordering::SeqCst);
+        while done.load(Ordering::SeqCst) < 2 {
+            session.poll(&mut game).unwrap();
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        (checksum, session.rollbacks())
+    }
+
+    #[test]
+    fn peers_agree_over_a_slow_lossy_link() {
+        let conditions = LinkConditions {
+            latency: Duration::from_millis(40),
+            jitter: Duration::from_millis(20),
+            loss: 0.1,
+        };
+        let mut host_link = Link::bind(0).unwrap();
+        host_link.set_conditions(conditions);
+        let port = host_link.port().unwrap();
+        let mut join_link = Link::bind(0).unwrap();
+        join_link.set_conditions(conditions);
+        let done = Arc::new(AtomicUsize::new(0));
+
+        let host_done = done.clone();
+        let host = std::thread::spawn(move || {
+            let world = World::new(99, GameSettings::default());
+            let session = RollbackSession::host(host_link, &world, Duration::from_secs(5)).unwrap();
+            play(session, host_done)
+        });
+        let address = format!("127.0.0.1:{}", port).parse().unwrap();
+        let session = RollbackSession::join(join_link, address, Duration::from_secs(5)).unwrap();
+        let (joiner_checksum, joiner_rollbacks) = play(session, done);
+        let (host_checksum, host_rollbacks) = host.join().unwrap();
+
+        assert_eq!(host_checksum, joiner_checksum);
+        assert!(host_rollbacks > 0 && joiner_rollbacks > 0);
+    }
+
+    #[test]
+    fn rewinds_across_a_restart_while_recording() {
+        let settings = GameSettings {
+            left_ai: Option::Some(Difficulty::Easy),
+            right_ai: Option::Some(Difficulty::Easy),
+            rules: MatchRules {
+                points_to_win: 1,
+                ..MatchRules::classic()
+            },
+            ..GameSettings::default()
+        };
+        let mut world = World::new(5, settings);
+        world.start_recording().unwrap();
+        let mut game = Game::new(world);
+        game.start();
+        let step = Duration::from_micros(FIXED_UPDATE_TIME_MICROS_U64);
+        while !matches!(game.state(), GameState::MatchOver { .. }) {
+            game.step(step);
+        }
+        let before = game.snapshot();
+        game.press(Action::Serve);
+        for _ in 0..10 {
+            game.step(step);
+        }
+        assert_ne!(game.world().seed(), before.world.seed);
+
+        game.rewind(&before).unwrap();
+        assert_eq!(game.snapshot(), before);
+        let recordings = game.take_recordings();
+        assert_eq!(recordings.len(), 1);
+        assert_eq!(recordings[0].ticks, before.world.fixed_updates);
+    }
+}
@@ -0,0 +1,304 @@
+use rand::Rng;
+
+use crate::actors::scoreboard::ScoreboardData;
+use crate::controllers::local::Side;
+use crate::math_helper::GameRng;
+
+/// Who serves the next point.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Server {
+    /// A coin toss before every point
+    Random,
+    /// The left player serves first, then the serve changes sides every `every` points
+    Alternate { every: u32 },
+    /// Whoever won the last point
+    PointWinner,
+    /// Whoever lost the last point
+    PointLoser,
+}
+
+/// Which way a serve sends the ball.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ServeDirection {
+    /// Towards the receiver, so the server's opponent plays the first shot
+    AwayFromServer,
+    /// Towards the server, who plays the first shot
+    TowardsServer,
+    /// Either way, regardless of who serves
+    Random,
+}
+
+/// How a match is scored and served. Everything here is data, so any set of rules can be
+/// described in a config file and recorded in a replay.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct MatchRules {
+    /// Points needed to win a game
+    pub points_to_win: u32,
+    /// How far ahead a player has to be to win a game, e.g. 2 for win-by-two
+    pub win_by: u32,
+    /// Number of games in the match. Whoever wins more than half of them wins the match.
+    pub best_of: u32,
+    pub server: Server,
+    pub serve_direction: ServeDirection,
+    /// Seconds the ball waits in the middle before each serve
+    pub serve_delay: f32,
+}
+
+impl MatchRules {
+    /// Arcade rules: a single game to 11, served in a random direction.
+    pub fn classic() -> MatchRules {
+        MatchRules {
+            points_to_win: 11,
+            win_by: 1,
+            best_of: 1,
+            server: Server::Random,
+            serve_direction: ServeDirection::Random,
+            serve_delay: 1.0,
+        }
+    }
+
+    /// Table tennis rules: best of five games to 11, win by two, serving twice in a row.
+    pub fn table_tennis() -> MatchRules {
+        MatchRules {
+            points_to_win: 11,
+            win_by: 2,
+            best_of: 5,
+            server: Server::Alternate { every: 2 },
+            serve_direction: ServeDirection::AwayFromServer,
+            serve_delay: 1.5,
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<MatchRules> {
+        match name {
+            "classic" => Option::Some(MatchRules::classic()),
+            "table_tennis" => Option::Some(MatchRules::table_tennis()),
+            _ => Option::None,
+        }
+    }
+
+    /// Checks the rules describe a match that can be played to the end.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.points_to_win == 0 {
+            return Result::Err("points_to_win must be at least 1".to_string());
+        }
+        if self.win_by == 0 {
+            return Result::Err("win_by must be at least 1".to_string());
+        }
+        if self.best_of == 0 {
+            return Result::Err("best_of must be at least 1".to_string());
+        }
+        if let Server::Alternate { every: 0 } = self.server {
+            return Result::Err("serve_every must be at least 1".to_string());
+        }
+        if !(self.serve_delay >= 0.0 && self.serve_delay.is_finite()) {
+            return Result::Err("serve_delay must be zero or more seconds".to_string());
+        }
+        Result::Ok(())
+    }
+
+    pub fn games_to_win(&self) -> u32 {
+        self.best_of / 2 + 1
+    }
+
+    /// Who won the game with these points, if anyone has yet.
+    pub fn game_winner(&self, left: u32, right: u32) -> Option<Side> {
+        if left >= self.points_to_win && left >= right + self.win_by {
+            Option::Some(Side::Left)
+        } else if right >= self.points_to_win && right >= left + self.win_by {
+            Option::Some(Side::Right)
+        } else {
+            Option::None
+        }
+    }
+
+    pub fn match_winner(&self, scores: &ScoreboardData) -> Option<Side> {
+        let games = self.games_to_win();
+        if scores.left_games >= games {
+            Option::Some(Side::Left)
+        } else if scores.right_games >= games {
+            Option::Some(Side::Right)
+        } else {
+            Option::None
+        }
+    }
+
+    /// Gives `side` a point, starting a new game once it wins the current one.
+    pub fn award_point(&self, scores: &mut ScoreboardData, side: Side) {
+        match side {
+            Side::Left => scores.left_score += 1,
+            Side::Right => scores.right_score += 1,
+        }
+        scores.points_played += 1;
+
+        if let Option::Some(winner) = self.game_winner(scores.left_score, scores.right_score) {
+            match winner {
+                Side::Left => scores.left_games += 1,
+                Side::Right => scores.right_games += 1,
+            }
+            // Keep the final score of the match on the board
+            if self.match_winner(scores).is_none() {
+                scores.left_score = 0;
+                scores.right_score = 0;
+            }
+        }
+    }
+
+    /// Who serves after `points_played` points, the last of which was won by `point_winner`.
+    pub fn next_server(
+        &self,
+        points_played: u32,
+        point_winner: Option<Side>,
+        rng: &mut GameRng,
+    ) -> Side {
+        match (self.server, point_winner) {
+            (Server::Alternate { every }, _) => match (points_played / every.max(1)) % 2 {
+                0 => Side::Left,
+                _ => Side::Right,
+            },
+            (Server::PointWinner, Option::Some(winner)) => winner,
+            (Server::PointLoser, Option::Some(winner)) => opponent(winner),
+            // Nobody has won a point before the first serve
+            (Server::PointWinner, Option::None) | (Server::PointLoser, Option::None) => Side::Left,
+            (Server::Random, _) => random_side(rng),
+        }
+    }
+
+    /// The side a serve by `server` sends the ball towards.
+    pub fn serve_towards(&self, server: Side, rng: &mut GameRng) -> Side {
+        match self.serve_direction {
+            ServeDirection::AwayFromServer => opponent(server),
+            ServeDirection::TowardsServer => server,
+            ServeDirection::Random => random_side(rng),
+        }
+    }
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        MatchRules::classic()
+    }
+}
+
+impl Server {
+    pub fn name(self) -> &'static str {
+        match self {
+            Server::Random => "random",
+            Server::Alternate { .. } => "alternate",
+            Server::PointWinner => "point_winner",
+            Server::PointLoser => "point_loser",
+        }
+    }
+
+    /// Parses a server by name. `every` is only used by `alternate`.
+    pub fn from_name(name: &str, every: u32) -> Option<Server> {
+        match name {
+            "random" => Option::Some(Server::Random),
+            "alternate" => Option::Some(Server::Alternate { every }),
+            "point_winner" => Option::Some(Server::PointWinner),
+            "point_loser" => Option::Some(Server::PointLoser),
+            _ => Option::None,
+        }
+    }
+}
+
+impl ServeDirection {
+    pub fn name(self) -> &'static str {
+        match self {
+            ServeDirection::AwayFromServer => "away_from_server",
+            ServeDirection::TowardsServer => "towards_server",
+            ServeDirection::Random => "random",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ServeDirection> {
+        match name {
+            "away_from_server" => Option::Some(ServeDirection::AwayFromServer),
+            "towards_server" => Option::Some(ServeDirection::TowardsServer),
+            "random" => Option::Some(ServeDirection::Random),
+            _ => Option::None,
+        }
+    }
+}
+
+pub fn opponent(side: Side) -> Side {
+    match side {
+        Side::Left => Side::Right,
+        Side::Right => Side::Left,
+    }
+}
+
+fn random_side(rng: &mut GameRng) -> Side {
+    if rng.gen::<bool>() {
+        Side::Left
+    } else {
+        Side::Right
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn play(rules: &MatchRules, scores: &mut ScoreboardData, points: &[Side]) {
+        for side in points {
+            rules.award_point(scores, *side);
+        }
+    }
+
+    #[test]
+    fn win_by_two_plays_on_past_deuce() {
+        let rules = MatchRules::table_tennis();
+        let mut scores = ScoreboardData::new();
+        for _ in 0..10 {
+            play(&rules, &mut scores, &[Side::Left, Side::Right]);
+        }
+        // 11:10 and 11:11 don't decide the game
+        play(&rules, &mut scores, &[Side::Left]);
+        assert_eq!((scores.left_score, scores.right_score), (11, 10));
+        assert_eq!(scores.left_games, 0);
+        play(&rules, &mut scores, &[Side::Right, Side::Right]);
+        assert_eq!((scores.left_score, scores.right_score), (11, 12));
+        assert_eq!(rules.game_winner(11, 12), Option::None);
+        play(&rules, &mut scores, &[Side::Right]);
+        assert_eq!((scores.left_games, scores.right_games), (0, 1));
+        assert_eq!((scores.left_score, scores.right_score), (0, 0));
+        assert_eq!(rules.game_winner(11, 9), Option::Some(Side::Left));
+    }
+
+    #[test]
+    fn best_of_five_ends_once_a_player_wins_three_games() {
+        let rules = MatchRules {
+            win_by: 1,
+            ..MatchRules::table_tennis()
+        };
+        let mut scores = ScoreboardData::new();
+        let game = [Side::Left; 11];
+        play(&rules, &mut scores, &game);
+        play(&rules, &mut scores, &[Side::Right; 11]);
+        play(&rules, &mut scores, &game);
+        assert_eq!((scores.left_games, scores.right_games), (2, 1));
+        assert_eq!(rules.match_winner(&scores), Option::None);
+        play(&rules, &mut scores, &game[1..]);
+        assert_eq!(rules.match_winner(&scores), Option::None);
+        play(&rules, &mut scores, &[Side::Left]);
+        assert_eq!(rules.match_winner(&scores), Option::Some(Side::Left));
+        // The final score stays on the board
+        assert_eq!((scores.left_score, scores.right_score), (11, 0));
+        assert_eq!(scores.points_played, 44);
+    }
+
+    #[test]
+    fn alternate_serve_changes_sides_every_two_points() {
+        let rules = MatchRules::table_tennis();
+        let mut rng = GameRng::seed_from_u64(1);
+        let servers: Vec<Side> = (0..6)
+            .map(|points| rules.next_server(points, Option::Some(Side::Right), &mut rng))
+            .collect();
+        let (l, r) = (Side::Left, Side::Right);
+        assert_eq!(servers, [l, l, r, r, l, l]);
+        assert_eq!(rules.serve_towards(Side::Left, &mut rng), Side::Right);
+    }
+}
@@ -0,0 +1,62 @@
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+use crate::audio::{AudioSettings, Mixer, Sound, VoiceParams, CHANNELS};
+use crate::event::GameEvent;
+use crate::tuning::BallTuning;
+
+pub const SAMPLE_RATE: i32 = 44_100;
+
+struct MixerCallback {
+    mixer: Mixer,
+}
+
+impl AudioCallback for MixerCallback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.mixer.render(out);
+    }
+}
+
+/// Plays the game's sounds through an SDL audio device. With `SDL_AUDIODRIVER=dummy`
+/// the mixer still runs, the samples just go nowhere.
+pub struct SdlAudio {
+    device: AudioDevice<MixerCallback>,
+}
+
+impl SdlAudio {
+    pub fn new(subsystem: &AudioSubsystem, settings: AudioSettings) -> Result<SdlAudio, String> {
+        let desired = AudioSpecDesired {
+            freq: Option::Some(SAMPLE_RATE),
+            channels: Option::Some(CHANNELS as u8),
+            samples: Option::None,
+        };
+        let device = subsystem.open_playback(Option::None, &desired, |spec| MixerCallback {
+            mixer: Mixer::new(spec.freq, settings),
+        })?;
+        device.resume();
+        Result::Ok(SdlAudio { device })
+    }
+
+    pub fn play(&mut self, sound: Sound, params: VoiceParams) {
+        self.device.lock().mixer.play(sound, params);
+    }
+
+    /// Plays the sound of every event that has one, following the ball tuned by `tuning`.
+    pub fn play_events(&mut self, events: &[GameEvent], tuning: &BallTuning) {
+        let mut callback = self.device.lock();
+        for event in events.iter() {
+            if let Option::Some((sound, params)) = Sound::for_event(event, tuning) {
+                callback.mixer.play(sound, params);
+            }
+        }
+    }
+
+    pub fn toggle_mute(&mut self) {
+        let mut callback = self.device.lock();
+        let mut settings = callback.mixer.settings();
+        settings.muted = !settings.muted;
+        callback.mixer.set_settings(settings);
+    }
+}
@@ -0,0 +1,496 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use rand::SeedableRng;
+
+use crate::actor::{ActorId, ActorState, Vec2};
+use crate::actors::ball::{BallState, Phase};
+use crate::actors::player_paddle::PaddleState;
+use crate::actors::scoreboard::ScoreboardData;
+use crate::controller::ControllerState;
+use crate::controllers::ai::AiState;
+use crate::controllers::local::Side;
+use crate::game::{GameSnapshot, GameState};
+use crate::math_helper::GameRng;
+use crate::replay::{parse_next, parse_setting, settings_to_text, validate_settings};
+use crate::world::{GameSettings, WorldSnapshot, FIXED_UPDATE_RATE, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub const DEFAULT_SNAPSHOT_PATH: &str = "pong.snapshot";
+pub const SNAPSHOT_VERSION: u32 = 3;
+const SNAPSHOT_MAGIC: &str = "pong-snapshot";
+
+/// Snapshots are stored as text like a replay: the seed and settings one `key value` per
+/// line, then the game state, clocks, random generator and recording checksum, then one
+/// `actor <id> <state>` line per actor in update order. Numbers read back exactly, so a
+/// loaded snapshot plays on just like the game it was saved from.
+impl GameSnapshot {
+    pub fn to_text(&self) -> String {
+        let world = &self.world;
+        let mut s = format!("{} {}\n", SNAPSHOT_MAGIC, SNAPSHOT_VERSION);
+        s += &format!("seed {}\n", world.seed);
+        s += &format!("fixed_update_rate {}\n", FIXED_UPDATE_RATE);
+        s += &format!("screen {} {}\n", SCREEN_WIDTH, SCREEN_HEIGHT);
+        s += &settings_to_text(&world.settings);
+        s += &format!("game {}\n", game_state_text(self.state));
+        s += &format!("fixed_updates {}\n", world.fixed_updates);
+        s += &format!(
+            "time_since_fixed_update {}\n",
+            world.time_since_fixed_update.as_nanos()
+        );
+        let seed: String = world
+            .rng
+            .get_seed()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        s += &format!(
+            "rng {} {} {}\n",
+            seed,
+            world.rng.get_stream(),
+            world.rng.get_word_pos()
+        );
+        s += &format!("ids_handed_out {}\n", world.ids_handed_out);
+        match world.history {
+            Option::Some(h) => s += &format!("history {}\n", h),
+            Option::None => s += "history none\n",
+        }
+        for (id, state) in world.actors.iter() {
+            s += &format!("actor {} {}\n", id.as_u64(), actor_state_text(state));
+        }
+        s
+    }
+
+    pub fn from_text(text: &str) -> Result<GameSnapshot, String> {
+        let mut lines = text.lines().enumerate();
+
+        let version = match lines.next() {
+            Option::Some((_, header)) => match header.split_once(' ') {
+                Option::Some((SNAPSHOT_MAGIC, version)) => version
+                    .parse::<u32>()
+                    .map_err(|e| format!("Invalid snapshot version: {}", e))?,
+                _ => return Result::Err("Not a snapshot file".to_string()),
+            },
+            Option::None => return Result::Err("Snapshot file is empty".to_string()),
+        };
+        if version != SNAPSHOT_VERSION {
+            return Result::Err(format!(
+                "Unsupported snapshot version {}, expected {}",
+                version, SNAPSHOT_VERSION
+            ));
+        }
+
+        let mut seed = 0;
+        let mut settings = GameSettings::default();
+        let mut fixed_update_rate = FIXED_UPDATE_RATE;
+        let mut screen = (SCREEN_WIDTH, SCREEN_HEIGHT);
+        let mut state = GameState::Title;
+        let mut fixed_updates = 0;
+        let mut time_since_fixed_update = Duration::ZERO;
+        let mut rng: Option<GameRng> = Option::None;
+        let mut ids_handed_out = 0;
+        let mut history = Option::None;
+        let mut actors = Vec::new();
+        for (n, line) in lines {
+            let err = |e: &dyn std::fmt::Display| format!("Snapshot line {}: {}", n + 1, e);
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Option::Some("seed") => seed = parse_next(&mut parts).map_err(|e| err(&e))?,
+                Option::Some("fixed_update_rate") => {
+                    fixed_update_rate = parse_next(&mut parts).map_err(|e| err(&e))?;
+                }
+                Option::Some("screen") => {
+                    screen.0 = parse_next(&mut parts).map_err(|e| err(&e))?;
+                    screen.1 = parse_next(&mut parts).map_err(|e| err(&e))?;
+                }
+                Option::Some("game") => {
+                    state = parse_game_state(&mut parts).map_err(|e| err(&e))?;
+                }
+                Option::Some("fixed_updates") => {
+                    fixed_updates = parse_next(&mut parts).map_err(|e| err(&e))?;
+                }
+                Option::Some("time_since_fixed_update") => {
+                    let nanos = parse_next(&mut parts).map_err(|e| err(&e))?;
+                    time_since_fixed_update = Duration::from_nanos(nanos);
+                }
+                Option::Some("rng") => {
+                    rng = Option::Some(parse_rng(&mut parts).map_err(|e| err(&e))?);
+                }
+                Option::Some("ids_handed_out") => {
+                    ids_handed_out = parse_next(&mut parts).map_err(|e| err(&e))?;
+                }
+                Option::Some("history") => {
+                    history = match parts.next() {
+                        Option::Some("none") => Option::None,
+                        Option::Some(h) => Option::Some(h.parse().map_err(|e| err(&e))?),
+                        Option::None => return Result::Err(err(&"missing value")),
+                    };
+                }
+                Option::Some("actor") => {
+                    let id = ActorId::from_u64(parse_next(&mut parts).map_err(|e| err(&e))?);
+                    let state = parse_actor_state(&mut parts).map_err(|e| err(&e))?;
+                    actors.push((id, state));
+                }
+                Option::Some(key) => {
+                    let known =
+                        parse_setting(key, &mut parts, &mut settings).map_err(|e| err(&e))?;
+                    if !known {
+                        return Result::Err(err(&format!("unknown entry {}", key)));
+                    }
+                }
+                Option::None => {}
+            }
+        }
+        validate_settings(&settings).map_err(|e| format!("Invalid snapshot {}", e))?;
+        if fixed_update_rate != FIXED_UPDATE_RATE || screen != (SCREEN_WIDTH, SCREEN_HEIGHT) {
+            return Result::Err("Snapshot was saved with different game settings".to_string());
+        }
+
+        Result::Ok(GameSnapshot {
+            world: WorldSnapshot {
+                seed,
+                settings,
+                fixed_updates,
+                time_since_fixed_update,
+                rng: rng.ok_or("Snapshot has no random generator state".to_string())?,
+                ids_handed_out,
+                actors,
+                history,
+            },
+            state,
+        })
+    }
+
+    /// A hash of everything in the snapshot, the same for two games in the same state
+    /// running the same build, e.g. to check that network peers haven't drifted apart.
+    pub fn checksum(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.to_text().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_text())
+            .map_err(|e| format!("Failed to write snapshot to {}: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<GameSnapshot, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read snapshot at {}: {}", path, e))?;
+        GameSnapshot::from_text(&text).map_err(|e| format!("{} in {}", e, path))
+    }
+}
+
+fn parse_rng<'a, I>(parts: &mut I) -> Result<GameRng, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let hex: String = parse_next(parts)?;
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Result::Err(format!("invalid random seed {}", hex));
+    }
+    let mut seed = [0u8; 32];
+    for (i, byte) in seed.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|e| format!("invalid random seed {}: {}", hex, e))?;
+    }
+    let mut rng = GameRng::from_seed(seed);
+    rng.set_stream(parse_next(parts)?);
+    rng.set_word_pos(parse_next(parts)?);
+    Result::Ok(rng)
+}
+
+fn actor_state_text(state: &Option<ActorState>) -> String {
+    match state {
+        Option::None => "none".to_string(),
+        Option::Some(ActorState::Ball(b)) => {
+            let phase = match b.phase {
+                Phase::Waiting { serve_in, towards } => {
+                    format!("waiting {} {}", serve_in, side_name(towards))
+                }
+                Phase::InPlay => "in_play".to_string(),
+                Phase::MatchOver => "match_over".to_string(),
+            };
+            format!(
+                "ball {} {} {} {} {} {}",
+                vec2_text(b.position),
+                vec2_text(b.initial_position),
+                vec2_text(b.velocity),
+                b.rally_hits,
+                b.alive,
+                phase
+            )
+        }
+        Option::Some(ActorState::Paddle(p)) => {
+            let controller = match p.controller {
+                Option::None => "none".to_string(),
+                Option::Some(ControllerState::Ai(ai)) => format!(
+                    "ai {} {} {} {}",
+                    ai.approaching,
+                    match ai.last_ball_position {
+                        Option::Some(p) => vec2_text(p),
+                        Option::None => "none".to_string(),
+                    },
+                    ai.reaction_left,
+                    match ai.target_y {
+                        Option::Some(y) => y.to_string(),
+                        Option::None => "none".to_string(),
+                    }
+                ),
+                Option::Some(ControllerState::Network { last }) => format!("network {}", last),
+                Option::Some(ControllerState::Replay { tick }) => format!("replay {}", tick),
+            };
+            format!(
+                "paddle {} {} {}",
+                vec2_text(p.position),
+                vec2_text(p.velocity),
+                controller
+            )
+        }
+        Option::Some(ActorState::Scoreboard(data)) => format!("scoreboard {}", scores_text(data)),
+        Option::Some(ActorState::BallSpawner { spawn_in }) => {
+            format!("ball_spawner {}", spawn_in)
+        }
+    }
+}
+
+fn parse_actor_state<'a, I>(parts: &mut I) -> Result<Option<ActorState>, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let state = match parts.next() {
+        Option::Some("none") => return Result::Ok(Option::None),
+        Option::Some("ball") => {
+            let position = parse_vec2(parts)?;
+            let initial_position = parse_vec2(parts)?;
+            let velocity = parse_vec2(parts)?;
+            let rally_hits = parse_next(parts)?;
+            let alive = parse_next(parts)?;
+            let phase = match parts.next() {
+                Option::Some("waiting") => Phase::Waiting {
+                    serve_in: parse_next(parts)?,
+                    towards: parse_side(parts)?,
+                },
+                Option::Some("in_play") => Phase::InPlay,
+                Option::Some("match_over") => Phase::MatchOver,
+                Option::Some(other) => return Result::Err(format!("unknown ball phase {}", other)),
+                Option::None => return Result::Err("missing value".to_string()),
+            };
+            ActorState::Ball(BallState {
+                position,
+                initial_position,
+                velocity,
+                phase,
+                rally_hits,
+                alive,
+            })
+        }
+        Option::Some("paddle") => {
+            let position = parse_vec2(parts)?;
+            let velocity = parse_vec2(parts)?;
+            let controller = match parts.next() {
+                Option::Some("none") => Option::None,
+                Option::Some("ai") => {
+                    let approaching = parse_next(parts)?;
+                    let last_ball_position = match parts.next() {
+                        Option::Some("none") => Option::None,
+                        Option::Some(x) => Option::Some(Vec2 {
+                            x: x.parse().map_err(|e| format!("{}", e))?,
+                            y: parse_next(parts)?,
+                        }),
+                        Option::None => return Result::Err("missing value".to_string()),
+                    };
+                    let reaction_left = parse_next(parts)?;
+                    let target_y = match parts.next() {
+                        Option::Some("none") => Option::None,
+                        Option::Some(y) => Option::Some(y.parse().map_err(|e| format!("{}", e))?),
+                        Option::None => return Result::Err("missing value".to_string()),
+                    };
+                    Option::Some(ControllerState::Ai(AiState {
+                        approaching,
+                        last_ball_position,
+                        reaction_left,
+                        target_y,
+                    }))
+                }
+                Option::Some("network") => Option::Some(ControllerState::Network {
+                    last: parse_next(parts)?,
+                }),
+                Option::Some("replay") => Option::Some(ControllerState::Replay {
+                    tick: parse_next(parts)?,
+                }),
+                Option::Some(other) => return Result::Err(format!("unknown controller {}", other)),
+                Option::None => return Result::Err("missing value".to_string()),
+            };
+            ActorState::Paddle(PaddleState {
+                position,
+                velocity,
+                controller,
+            })
+        }
+        Option::Some("scoreboard") => ActorState::Scoreboard(parse_scores(parts)?),
+        Option::Some("ball_spawner") => ActorState::BallSpawner {
+            spawn_in: parse_next(parts)?,
+        },
+        Option::Some(other) => return Result::Err(format!("unknown actor {}", other)),
+        Option::None => return Result::Err("missing value".to_string()),
+    };
+    Result::Ok(Option::Some(state))
+}
+
+fn vec2_text(v: Vec2) -> String {
+    format!("{} {}", v.x, v.y)
+}
+
+pub fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Left => "left",
+        Side::Right => "right",
+    }
+}
+
+pub fn parse_side<'a, I>(parts: &mut I) -> Result<Side, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    match parts.next() {
+        Option::Some("left") => Result::Ok(Side::Left),
+        Option::Some("right") => Result::Ok(Side::Right),
+        Option::Some(other) => Result::Err(format!("unknown side {}", other)),
+        Option::None => Result::Err("missing value".to_string()),
+    }
+}
+
+pub fn parse_vec2<'a, I>(parts: &mut I) -> Result<Vec2, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    Result::Ok(Vec2 {
+        x: parse_next(parts)?,
+        y: parse_next(parts)?,
+    })
+}
+
+pub fn scores_text(s: &ScoreboardData) -> String {
+    format!(
+        "{} {} {} {} {}",
+        s.left_score, s.right_score, s.left_games, s.right_games, s.points_played
+    )
+}
+
+pub fn parse_scores<'a, I>(parts: &mut I) -> Result<ScoreboardData, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    Result::Ok(ScoreboardData {
+        left_score: parse_next(parts)?,
+        right_score: parse_next(parts)?,
+        left_games: parse_next(parts)?,
+        right_games: parse_next(parts)?,
+        points_played: parse_next(parts)?,
+    })
+}
+
+/// The game state as text, with the time left after a point in nanoseconds.
+pub fn game_state_text(state: GameState) -> String {
+    match state {
+        GameState::Title => "title".to_string(),
+        GameState::Playing => "playing".to_string(),
+        GameState::Paused => "paused".to_string(),
+        GameState::PointScored { scorer, remaining } => {
+            format!("point {} {}", side_name(scorer), remaining.as_nanos())
+        }
+        GameState::MatchOver { winner } => format!("over {}", side_name(winner)),
+    }
+}
+
+pub fn parse_game_state<'a, I>(parts: &mut I) -> Result<GameState, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    match parts.next() {
+        Option::Some("title") => Result::Ok(GameState::Title),
+        Option::Some("playing") => Result::Ok(GameState::Playing),
+        Option::Some("paused") => Result::Ok(GameState::Paused),
+        Option::Some("point") => Result::Ok(GameState::PointScored {
+            scorer: parse_side(parts)?,
+            remaining: Duration::from_nanos(parse_next(parts)?),
+        }),
+        Option::Some("over") => Result::Ok(GameState::MatchOver {
+            winner: parse_side(parts)?,
+        }),
+        Option::Some(other) => Result::Err(format!("unknown game state {}", other)),
+        Option::None => Result::Err("missing value".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controllers::ai::Difficulty;
+    use crate::game::Game;
+    use crate::input::Action;
+    use crate::world::{World, FIXED_UPDATE_TIME_MICROS_U64};
+
+    const STEP: Duration = Duration::from_micros(FIXED_UPDATE_TIME_MICROS_U64);
+
+    /// A computer match with several balls in play, stopped between two fixed updates
+    fn game_in_progress() -> Game {
+        let mut settings = GameSettings {
+            left_ai: Option::Some(Difficulty::Hard),
+            right_ai: Option::Some(Difficulty::Easy),
+            ..GameSettings::default()
+        };
+        settings.multi_ball.max_balls = 3;
+        settings.multi_ball.spawn_interval = 2.5;
+        let mut world = World::new(99, settings);
+        world.start_recording().unwrap();
+        let mut game = Game::new(world);
+        game.press(Action::Serve);
+        for _ in 0..1500 {
+            game.step(STEP);
+        }
+        game.step(Duration::from_nanos(1_234_567));
+        game
+    }
+
+    #[test]
+    fn snapshot_survives_its_text_form() {
+        let snapshot = game_in_progress().snapshot();
+        assert!(snapshot.world.history.is_some());
+        assert!(!snapshot.world.time_since_fixed_update.is_zero());
+
+        let text = snapshot.to_text();
+        let loaded = GameSnapshot::from_text(&text).unwrap();
+        assert_eq!(loaded, snapshot);
+        assert_eq!(loaded.to_text(), text);
+        assert_eq!(loaded.checksum(), snapshot.checksum());
+
+        assert!(GameSnapshot::from_text("").is_err());
+        assert!(GameSnapshot::from_text("pong-replay 7\n").is_err());
+        let old = text.replacen(&SNAPSHOT_VERSION.to_string(), "1", 1);
+        assert!(GameSnapshot::from_text(&old).is_err());
+    }
+
+    #[test]
+    fn restored_game_plays_on_like_the_original() {
+        let mut original = game_in_progress();
+        let text = original.snapshot().to_text();
+
+        let mut restored = Game::new(World::new(1, GameSettings::default()));
+        restored
+            .restore(&GameSnapshot::from_text(&text).unwrap())
+            .unwrap();
+        for _ in 0..3000 {
+            original.step(STEP);
+            restored.step(STEP);
+        }
+        assert_eq!(restored.state(), original.state());
+        assert_eq!(restored.world().scores(), original.world().scores());
+        assert_eq!(
+            restored.snapshot().world.actors,
+            original.snapshot().world.actors
+        );
+    }
+}
@@ -0,0 +1,108 @@
+/// How the ball plays. Changes how a match plays out, so replays record it too.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct BallTuning {
+    /// Degrees from the horizontal the ball leaves at when hit with the very end of a paddle
+    pub max_bounce_angle: f32,
+    /// Share of the paddle's vertical speed passed on to the ball when it is hit
+    pub paddle_influence: f32,
+    /// Pixels per second
+    pub initial_speed: f32,
+    pub max_speed: f32,
+    /// Multiplies the speed with every paddle return
+    pub paddle_speed_up: f32,
+    pub wall_speed_up: f32,
+    /// After `n` returns the ball has gained `rally_speed_up * n^rally_exponent`
+    pub rally_speed_up: f32,
+    pub rally_exponent: f32,
+    pub speed_up_per_second: f32,
+}
+
+impl BallTuning {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.max_bounce_angle > 0.0 && self.max_bounce_angle < 90.0) {
+            return Result::Err("max_bounce_angle must be between 0 and 90 degrees".to_string());
+        }
+        if !(self.paddle_influence >= 0.0 && self.paddle_influence.is_finite()) {
+            return Result::Err("paddle_influence must be zero or more".to_string());
+        }
+        if !(self.initial_speed > 0.0 && self.initial_speed.is_finite()) {
+            return Result::Err("initial_speed must be more than zero".to_string());
+        }
+        if !(self.max_speed >= self.initial_speed && self.max_speed.is_finite()) {
+            return Result::Err("max_speed must be at least initial_speed".to_string());
+        }
+        if !(self.paddle_speed_up > 0.0 && self.paddle_speed_up.is_finite()) {
+            return Result::Err("paddle_speed_up must be more than zero".to_string());
+        }
+        if !(self.wall_speed_up > 0.0 && self.wall_speed_up.is_finite()) {
+            return Result::Err("wall_speed_up must be more than zero".to_string());
+        }
+        if !(self.rally_speed_up >= 0.0 && self.rally_speed_up.is_finite()) {
+            return Result::Err("rally_speed_up must be zero or more".to_string());
+        }
+        if !(self.rally_exponent > 0.0 && self.rally_exponent.is_finite()) {
+            return Result::Err("rally_exponent must be more than zero".to_string());
+        }
+        if !(self.speed_up_per_second >= 0.0 && self.speed_up_per_second.is_finite()) {
+            return Result::Err("speed_up_per_second must be zero or more".to_string());
+        }
+        Result::Ok(())
+    }
+
+    pub fn rally_speed(&self, hits: u32) -> f32 {
+        self.rally_speed_up * (hits as f32).powf(self.rally_exponent)
+    }
+}
+
+impl Default for BallTuning {
+    fn default() -> Self {
+        BallTuning {
+            max_bounce_angle: 60.0,
+            paddle_influence: 0.25,
+            initial_speed: 125.0,
+            max_speed: 1000.0,
+            paddle_speed_up: 1.05,
+            wall_speed_up: 1.05,
+            rally_speed_up: 0.0,
+            rally_exponent: 1.0,
+            speed_up_per_second: 0.0,
+        }
+    }
+}
+
+/// Puts more than one ball in play at a time.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct MultiBall {
+    /// Most balls in play at once, 1 turns multi-ball off
+    pub max_balls: u32,
+    /// Seconds between new balls while any ball is in play, 0 for none
+    pub spawn_interval: f32,
+    /// Adds a ball after this many returns in a rally, 0 for none
+    pub spawn_every_returns: u32,
+}
+
+impl MultiBall {
+    pub fn enabled(&self) -> bool {
+        self.max_balls > 1
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_balls == 0 {
+            return Result::Err("max_balls must be at least 1".to_string());
+        }
+        if !(self.spawn_interval >= 0.0 && self.spawn_interval.is_finite()) {
+            return Result::Err("spawn_interval must be zero or more seconds".to_string());
+        }
+        Result::Ok(())
+    }
+}
+
+impl Default for MultiBall {
+    fn default() -> Self {
+        MultiBall {
+            max_balls: 1,
+            spawn_interval: 10.0,
+            spawn_every_returns: 0,
+        }
+    }
+}
@@ -0,0 +1,655 @@
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use rand::SeedableRng;
+
+use crate::actor::{Actor, ActorId, ActorState, UpdateInfo, Vec2};
+use crate::actors::ball::Ball;
+use crate::actors::ball_spawner::BallSpawner;
+use crate::actors::player_paddle::PlayerPaddle;
+use crate::actors::scoreboard::{Scoreboard, ScoreboardData};
+use crate::actors::wall::{Wall, WallType};
+use crate::controller::Controller;
+use crate::controllers::ai::{AiController, Difficulty};
+use crate::controllers::local::{LocalController, Side};
+use crate::controllers::network::NetworkController;
+use crate::controllers::replay::ReplayController;
+use crate::event::GameEvent;
+use crate::math_helper::GameRng;
+use crate::renderer::{Color, Renderer};
+use crate::replay::Replay;
+use crate::rules::MatchRules;
+use crate::tuning::{BallTuning, MultiBall};
+
+pub const SCREEN_WIDTH: i16 = 800;
+pub const SCREEN_HEIGHT: i16 = 600;
+
+pub const FONT_PATH: &str = "fonts/roboto_mono/RobotoMono-VariableFont_wght.ttf";
+pub const FONT_SIZE: u16 = 40;
+
+pub const TARGET_FRAMERATE: f32 = 60.0;
+pub const TARGET_FRAMETIME_MICROS: u128 = (1.0 / TARGET_FRAMERATE * 1_000_000.0) as u128;
+
+pub const FIXED_UPDATE_RATE: f32 = 60.0;
+pub const FIXED_UPDATE_TIME_SECS_F32: f32 = 1.0 / FIXED_UPDATE_RATE;
+pub const FIXED_UPDATE_TIME_MICROS_U64: u64 = (FIXED_UPDATE_TIME_SECS_F32 * 1_000_000.0) as u64;
+pub const FIXED_UPDATE_TIME_MICROS_U128: u128 = FIXED_UPDATE_TIME_MICROS_U64 as u128;
+
+/// Options that change how a match plays out, so replays have to record them.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct GameSettings {
+    /// Lets the computer control the left paddle
+    pub left_ai: Option<Difficulty>,
+    /// Lets the computer control the right paddle
+    pub right_ai: Option<Difficulty>,
+    /// Lets a player on another machine control this paddle, instead of the computer or keyboard
+    pub remote: Option<Side>,
+    pub rules: MatchRules,
+    pub ball: BallTuning,
+    pub multi_ball: MultiBall,
+}
+
+/// Everything a world needs to go back to an earlier fixed update: the state of every
+/// actor along with the random generator and clocks.
+#[derive(Clone, PartialEq, Debug)]
+pub struct WorldSnapshot {
+    pub seed: u64,
+    pub settings: GameSettings,
+    pub fixed_updates: u64,
+    pub time_since_fixed_update: Duration,
+    pub rng: GameRng,
+    pub ids_handed_out: u64,
+    /// Every actor in update order. Actors that never change have no state.
+    pub actors: Vec<(ActorId, Option<ActorState>)>,
+    /// `Replay::history_checksum` of the recording at the time, if there was one
+    pub history: Option<u64>,
+}
+
+/// Owns the actors and everything else the simulation depends on. Given the same seed and
+/// the same input on every fixed update, a world always plays out the same way.
+pub struct World {
+    pub update_info: UpdateInfo,
+    seed: u64,
+    settings: GameSettings,
+    time_since_fixed_update: Duration,
+    fixed_updates: u64,
+    recording: Option<Replay>,
+    playback: Option<Replay>,
+    /// Feeds the `NetworkController` of the remote player's paddle
+    remote: Option<Sender<f32>>,
+    remote_intent: f32,
+    /// Every event since the start of the last step
+    events: Vec<GameEvent>,
+}
+
+impl World {
+    pub fn new(seed: u64, settings: GameSettings) -> World {
+        let (controller, sender) = NetworkController::new();
+        let mut world = World::with_remote(seed, settings, Box::new(controller));
+        if settings.remote.is_some() {
+            world.remote = Option::Some(sender);
+        }
+        world
+    }
+
+    /// Creates a world that ignores `actions_pressed` and `analog` and instead feeds every
+    /// fixed update the input from `replay`, stopping once all recorded updates have run.
+    pub fn from_replay(replay: Replay) -> World {
+        let intents = (0..replay.ticks)
+            .map(|t| replay.input_at(t).remote)
+            .collect();
+        let controller = ReplayController::new(intents);
+        let mut world = World::with_remote(replay.seed, replay.settings, Box::new(controller));
+        world.playback = Option::Some(replay);
+        world
+    }
+
+    /// `remote` moves the paddle on the side of `settings.remote`, if any.
+    fn with_remote(seed: u64, settings: GameSettings, remote: Box<dyn Controller>) -> World {
+        let mut update_info = UpdateInfo::new(GameRng::seed_from_u64(seed));
+        populate(&mut update_info, &settings, remote);
+
+        World {
+            update_info,
+            seed,
+            settings,
+            time_since_fixed_update: Duration::from_micros(0),
+            fixed_updates: 0,
+            recording: Option::None,
+            playback: Option::None,
+            remote: Option::None,
+            remote_intent: 0.0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Sets how the remote player's paddle moves from the next fixed update on, as an
+    /// intent from -1.0 to 1.0. Does nothing unless the settings have a remote player.
+    pub fn set_remote_intent(&mut self, intent: f32) {
+        if let Option::Some(sender) = &self.remote {
+            // The controller lives as long as the world
+            let _ = sender.send(intent);
+            self.remote_intent = intent;
+        }
+    }
+
+    /// Starts recording the input of every fixed update. Only a recording that starts
+    /// with the world can be played back, so this fails once the world has been updated.
+    pub fn start_recording(&mut self) -> Result<(), String> {
+        if self.fixed_updates != 0 {
+            return Result::Err("Recording must start before the first fixed update".to_string());
+        }
+        self.recording = Option::Some(Replay::new(self.seed, self.settings));
+        Result::Ok(())
+    }
+
+    /// Whether the input of every fixed update is being recorded.
+    pub fn recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn take_recording(&mut self) -> Option<Replay> {
+        self.recording.take()
+    }
+
+    /// Carries on recording into `replay`, taken from an earlier world of the same match.
+    pub fn resume_recording(&mut self, replay: Replay) -> Result<(), String> {
+        if replay.seed != self.seed || replay.settings != self.settings {
+            return Result::Err("Recording is of a different match".to_string());
+        }
+        self.recording = Option::Some(replay);
+        Result::Ok(())
+    }
+
+    /// Whether this world plays its input back from a replay.
+    pub fn replaying(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    /// Whether this world is playing a replay that has run out of recorded updates.
+    pub fn replay_finished(&self) -> bool {
+        match &self.playback {
+            Option::Some(replay) => self.fixed_updates >= replay.ticks,
+            Option::None => false,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn settings(&self) -> &GameSettings {
+        &self.settings
+    }
+
+    /// Number of fixed updates simulated so far.
+    pub fn fixed_updates(&self) -> u64 {
+        self.fixed_updates
+    }
+
+    pub fn snapshot(&self) -> WorldSnapshot {
+        let info = &self.update_info;
+        WorldSnapshot {
+            seed: self.seed,
+            settings: self.settings,
+            fixed_updates: self.fixed_updates,
+            time_since_fixed_update: self.time_since_fixed_update,
+            rng: info.rng.borrow().clone(),
+            ids_handed_out: info.ids_handed_out(),
+            actors: info
+                .ids()
+                .iter()
+                .zip(info.actors.iter())
+                .map(|(id, a)| (*id, a.borrow().save_state()))
+                .collect(),
+            history: self
+                .recording
+                .as_ref()
+                .map(|r| r.history_checksum(self.fixed_updates)),
+        }
+    }
+
+    /// Puts every actor back the way it was in `snapshot`, which has to come from a world
+    /// with the same seed and settings. Balls that have left since are recreated.
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) -> Result<(), String> {
+        if snapshot.seed != self.seed || snapshot.settings != self.settings {
+            return Result::Err("Snapshot is of a different match".to_string());
+        }
+        // The recording would be missing the input of every fixed update skipped
+        if let Option::Some(replay) = &self.recording {
+            if snapshot.fixed_updates > replay.ticks {
+                return Result::Err(
+                    "Can't load a snapshot from later in the match while recording".to_string(),
+                );
+            }
+            // Or hold the input of a different way of getting there
+            if snapshot.history != Option::Some(replay.history_checksum(snapshot.fixed_updates)) {
+                return Result::Err(
+                    "Can't load a snapshot from another run of the match while recording"
+                        .to_string(),
+                );
+            }
+        }
+        self.rewind(snapshot)
+    }
+
+    /// Goes back to `snapshot` from earlier in this world's own run, e.g. to simulate it
+    /// again with other input. Unlike `restore`, a recording is just cut back to it.
+    pub fn rewind(&mut self, snapshot: &WorldSnapshot) -> Result<(), String> {
+        if snapshot.seed != self.seed || snapshot.settings != self.settings {
+            return Result::Err("Snapshot is of a different match".to_string());
+        }
+
+        // Recreate what has left before touching anything, so a failure leaves the world as it was
+        let mut recreated = Vec::new();
+        for (id, state) in snapshot.actors.iter() {
+            if self.update_info.index_of(*id).is_some() {
+                continue;
+            }
+            match state {
+                Option::Some(ActorState::Ball(s)) => {
+                    let ball: Box<dyn Actor> = Box::new(Ball::from_state(s, &self.settings));
+                    recreated.push((*id, ball));
+                }
+                _ => {
+                    return Result::Err(format!(
+                        "Snapshot has an actor {:?} that can't be recreated",
+                        id
+                    ))
+                }
+            }
+        }
+
+        let mut current = self.update_info.take_actors();
+        current.append(&mut recreated);
+        let mut actors = Vec::new();
+        for (id, state) in snapshot.actors.iter() {
+            if let Option::Some(i) = current.iter().position(|(c, _)| c == id) {
+                let (id, mut actor) = current.swap_remove(i);
+                if let Option::Some(s) = state {
+                    actor.load_state(s);
+                }
+                actors.push((id, actor));
+            }
+        }
+        self.update_info.set_actors(actors, snapshot.ids_handed_out);
+
+        *self.update_info.rng.borrow_mut() = snapshot.rng.clone();
+        self.fixed_updates = snapshot.fixed_updates;
+        self.time_since_fixed_update = snapshot.time_since_fixed_update;
+        self.events.clear();
+        self.update_info.events.get_mut().clear();
+        if let Option::Some(replay) = &mut self.recording {
+            replay.truncate(self.fixed_updates);
+        }
+        Result::Ok(())
+    }
+
+    /// Everything that happened during the last step, oldest first.
+    pub fn events(&self) -> &[GameEvent] {
+        &self.events
+    }
+
+    /// Forgets the events of the last step, for frames where the world isn't stepped.
+    pub fn clear_events(&mut self) {
+        self.events.clear();
+    }
+
+    /// The current score, if the world has a scoreboard.
+    pub fn scores(&self) -> Option<ScoreboardData> {
+        self.update_info.find::<Scoreboard>().map(|s| s.data())
+    }
+
+    /// Who won the match, once someone has.
+    pub fn match_winner(&self) -> Option<Side> {
+        self.scores()
+            .and_then(|s| self.settings.rules.match_winner(&s))
+    }
+
+    /// Runs `update` on every actor for a frame that took `elapsed`, then as many
+    /// fixed updates as the accumulated time allows.
+    pub fn step(&mut self, elapsed: Duration) {
+        self.events.clear();
+        self.update_info.elapsed = elapsed;
+        self.update_info.elapsed_sec_f32 = elapsed.as_micros() as f32 / 1_000_000.0;
+
+        let mut i = 0;
+        while i < self.update_info.actors.len() {
+            self.update_info.current = self.update_info.id_at(i);
+            if let Option::Some(a) = self.update_info.actors.get(i) {
+                a.borrow_mut().update(&self.update_info);
+            }
+            self.dispatch_events();
+            i += 1;
+        }
+        self.update_info.current = Option::None;
+        self.update_info.apply_commands();
+
+        self.time_since_fixed_update += elapsed;
+        while self.time_since_fixed_update.as_micros() > FIXED_UPDATE_TIME_MICROS_U128 {
+            self.fixed_update();
+            self.time_since_fixed_update -= Duration::from_micros(FIXED_UPDATE_TIME_MICROS_U64);
+        }
+    }
+
+    /// Runs a single `fixed_update` on every actor, independent of any clock.
+    pub fn fixed_update(&mut self) {
+        if self.replay_finished() {
+            return;
+        }
+        if let Option::Some(replay) = &self.playback {
+            let input = replay.input_at(self.fixed_updates);
+            self.update_info.actions_pressed = input.actions.iter().copied().collect();
+            self.update_info.analog = input.analog;
+        }
+        if let Option::Some(replay) = &mut self.recording {
+            replay.record(
+                &self.update_info.actions_pressed,
+                self.update_info.analog,
+                self.remote_intent,
+            );
+        }
+
+        self.update_info.elapsed = Duration::from_micros(FIXED_UPDATE_TIME_MICROS_U64);
+        self.update_info.elapsed_sec_f32 = FIXED_UPDATE_TIME_SECS_F32;
+
+        let mut i = 0;
+        while i < self.update_info.actors.len() {
+            self.update_info.current = self.update_info.id_at(i);
+            if let Option::Some(a) = self.update_info.actors.get(i) {
+                a.borrow_mut().fixed_update(&self.update_info);
+            }
+            self.dispatch_events();
+            i += 1;
+        }
+        self.update_info.current = Option::None;
+        self.update_info.apply_commands();
+        self.fixed_updates += 1;
+    }
+
+    /// Hands out the events emitted so far, including any emitted while handling them.
+    /// Runs after every actor's update, so the next actor sees the world after them.
+    fn dispatch_events(&mut self) {
+        let updating = self.update_info.current;
+        loop {
+            let events = std::mem::take(self.update_info.events.get_mut());
+            if events.is_empty() {
+                break;
+            }
+            for event in events.iter() {
+                let mut i = 0;
+                while i < self.update_info.actors.len() {
+                    self.update_info.current = self.update_info.id_at(i);
+                    if let Option::Some(a) = self.update_info.actors.get(i) {
+                        a.borrow_mut().on_event(event, &self.update_info);
+                    }
+                    i += 1;
+                }
+            }
+            self.events.extend(events);
+        }
+        self.update_info.current = updating;
+    }
+
+    /// Clears the frame and draws every actor, without presenting it.
+    pub fn draw(&self, renderer: &mut dyn Renderer) -> Result<(), String> {
+        renderer.clear(Color::BLACK)?;
+
+        let mut i = 0;
+        while i < self.update_info.actors.len() {
+            if let Option::Some(a) = self.update_info.actors.get(i) {
+                let _ = a.borrow().draw(renderer);
+            }
+            i += 1;
+        }
+        Result::Ok(())
+    }
+}
+
+/// Adds the paddles, ball, walls and scoreboard of a regular match to `update_info`, with
+/// `remote` moving the remote player's paddle if there is one.
+fn populate(update_info: &mut UpdateInfo, settings: &GameSettings, remote: Box<dyn Controller>) {
+    let half_width = SCREEN_WIDTH as f32 / 2.0;
+    let half_height = SCREEN_HEIGHT as f32 / 2.0;
+
+    let mut left: Box<dyn Controller> = match settings.left_ai {
+        Option::Some(d) => Box::new(AiController::new(d)),
+        Option::None => Box::new(LocalController::left()),
+    };
+    let mut right: Box<dyn Controller> = match settings.right_ai {
+        Option::Some(d) => Box::new(AiController::new(d)),
+        Option::None => Box::new(LocalController::right()),
+    };
+    match settings.remote {
+        Option::Some(Side::Left) => left = remote,
+        Option::Some(Side::Right) => right = remote,
+        Option::None => {}
+    }
+
+    let position = Vec2 {
+        x: 40.0,
+        y: half_height,
+    };
+    let player = PlayerPaddle::new(&position, left);
+    update_info.add(Box::new(player));
+
+    let position = Vec2 {
+        x: SCREEN_WIDTH as f32 - 40.0,
+        y: half_height,
+    };
+    let player = PlayerPaddle::new(&position, right);
+    update_info.add(Box::new(player));
+
+    let ball = Ball::new(
+        &Vec2 {
+            x: half_width,
+            y: half_height,
+        },
+        settings,
+        &mut update_info.rng.borrow_mut(),
+    );
+    update_info.add(Box::new(ball));
+
+    let wall = Wall::new(
+        &Vec2 {
+            x: half_width,
+            y: -half_height,
+        },
+        SCREEN_WIDTH as f32,
+        SCREEN_HEIGHT as f32,
+        WallType::Regular,
+    );
+    update_info.add(Box::new(wall));
+
+    let wall = Wall::new(
+        &Vec2 {
+            x: half_width,
+            y: 3.0 * half_height,
+        },
+        SCREEN_WIDTH as f32,
+        SCREEN_HEIGHT as f32,
+        WallType::Regular,
+    );
+    update_info.add(Box::new(wall));
+
+    let wall = Wall::new(
+        &Vec2 {
+            x: -half_width,
+            y: half_height,
+        },
+        SCREEN_WIDTH as f32,
+        SCREEN_HEIGHT as f32,
+        WallType::Left,
+    );
+    update_info.add(Box::new(wall));
+
+    let wall = Wall::new(
+        &Vec2 {
+            x: 3.0 * half_width,
+            y: half_height,
+        },
+        SCREEN_WIDTH as f32,
+        SCREEN_HEIGHT as f32,
+        WallType::Right,
+    );
+    update_info.add(Box::new(wall));
+
+    let scoreboard = Scoreboard::new(
+        &Vec2 {
+            x: half_width,
+            y: 50.0,
+        },
+        settings.rules.best_of > 1,
+    );
+    update_info.add(Box::new(scoreboard));
+
+    if settings.multi_ball.enabled() {
+        let spawner = BallSpawner::new(
+            &Vec2 {
+                x: half_width,
+                y: half_height,
+            },
+            settings,
+        );
+        update_info.add(Box::new(spawner));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::controllers::ai::Difficulty;
+    use crate::headless::HeadlessRunner;
+
+    /// The computer plays both sides, so a match goes on without any input
+    fn computer_match() -> GameSettings {
+        GameSettings {
+            left_ai: Option::Some(Difficulty::Easy),
+            right_ai: Option::Some(Difficulty::Easy),
+            ..GameSettings::default()
+        }
+    }
+
+    #[test]
+    fn same_seed_plays_out_the_same() {
+        let mut first = HeadlessRunner::new(7, computer_match());
+        let mut second = HeadlessRunner::new(7, computer_match());
+        // Long enough for a few points, with rallies speeding the ball up
+        for _ in 0..120 {
+            first.run_for(Duration::from_secs(1));
+            second.run_for(Duration::from_secs(1));
+            assert_eq!(first.world().snapshot(), second.world().snapshot());
+        }
+        assert!(first.scores().unwrap().points_played > 0);
+        assert_eq!(first.scores(), second.scores());
+    }
+
+    #[test]
+    fn remote_paddle_is_recorded_and_played_back() {
+        let settings = GameSettings {
+            left_ai: Option::Some(Difficulty::Easy),
+            remote: Option::Some(Side::Right),
+            ..GameSettings::default()
+        };
+        let mut world = World::new(3, settings);
+        world.start_recording().unwrap();
+        let mut recorded = HeadlessRunner::with_world(world);
+        for frame in 0..60 * 60 {
+            let intent = [-1.0, 0.0, 1.0, 0.5][(frame / 40) % 4];
+            recorded.world_mut().set_remote_intent(intent);
+            recorded.step();
+        }
+        let replay = recorded.world_mut().take_recording().unwrap();
+        assert!(replay.inputs.iter().any(|c| c.remote == 0.5));
+
+        let mut playback = HeadlessRunner::with_world(World::from_replay(replay));
+        while !playback.world().replay_finished() {
+            playback.step();
+        }
+        assert_eq!(playback.scores(), recorded.scores());
+        assert!(recorded.scores().unwrap().points_played > 0);
+        let positions = |runner: &HeadlessRunner| -> Vec<Vec2> {
+            let info = &runner.world().update_info;
+            info.actors.iter().map(|a| *a.borrow().position()).collect()
+        };
+        assert_eq!(positions(&playback), positions(&recorded));
+    }
+
+    #[test]
+    fn recording_refuses_snapshots_from_another_run() {
+        let settings = GameSettings {
+            left_ai: Option::Some(Difficulty::Easy),
+            remote: Option::Some(Side::Right),
+            ..GameSettings::default()
+        };
+        let mut world = World::new(3, settings);
+        world.start_recording().unwrap();
+        let mut runner = HeadlessRunner::with_world(world);
+        let play = |runner: &mut HeadlessRunner, intent: f32| {
+            runner.world_mut().set_remote_intent(intent);
+            for _ in 0..100 {
+                runner.step();
+            }
+        };
+        play(&mut runner, 1.0);
+        let a = runner.world().snapshot();
+        play(&mut runner, 1.0);
+        let b = runner.world().snapshot();
+
+        // Going back along the same run is fine
+        runner.world_mut().restore(&a).unwrap();
+        play(&mut runner, -1.0);
+        play(&mut runner, -1.0);
+        assert!(runner.world_mut().restore(&b).is_err());
+        runner.world_mut().restore(&a).unwrap();
+    }
+
+    #[test]
+    fn events_follow_the_match() {
+        let mut settings = computer_match();
+        settings.rules.points_to_win = 2;
+        let mut runner = HeadlessRunner::new(7, settings);
+        let mut events = Vec::new();
+        while runner.world().match_winner().is_none() {
+            runner.step();
+            for event in runner.world().events() {
+                if let GameEvent::PointScored { scores, .. } = event {
+                    assert_eq!(Option::Some(*scores), runner.scores());
+                }
+                events.push(*event);
+            }
+            assert!(runner.simulated_time() < Duration::from_secs(600));
+        }
+
+        assert!(matches!(events[0], GameEvent::Serve { .. }));
+        let count = |matches: fn(&GameEvent) -> bool| events.iter().filter(|e| matches(e)).count();
+        assert!(count(|e| matches!(e, GameEvent::BallHitPaddle { .. })) > 0);
+        assert!(count(|e| matches!(e, GameEvent::BallHitWall { .. })) > 0);
+        let points = count(|e| matches!(e, GameEvent::PointScored { .. }));
+        assert_eq!(points as u32, runner.scores().unwrap().points_played);
+        // Every point but the last is followed by a serve
+        assert_eq!(count(|e| matches!(e, GameEvent::Serve { .. })), points);
+        match events[events.len() - 2..] {
+            [GameEvent::PointScored { scorer, scores }, GameEvent::MatchWon { winner, scores: s }] =>
+            {
+                assert_eq!(scorer, winner);
+                assert_eq!(scores, s);
+            }
+            _ => panic!("the match didn't end with its last point: {:?}", events),
+        }
+    }
+
+    #[test]
+    fn different_seeds_play_out_differently() {
+        let mut first = HeadlessRunner::new(7, computer_match());
+        let mut second = HeadlessRunner::new(8, computer_match());
+        first.run_for(Duration::from_secs(5));
+        second.run_for(Duration::from_secs(5));
+        assert_ne!(
+            first.world().snapshot().actors,
+            second.world().snapshot().actors
+        );
+    }
+}